@@ -0,0 +1,123 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! Constant-time integer operations on byte strings, such as the bytes of a
+//! value block.
+//!
+//! The bytes are interpreted as an unsigned integer, in an explicitly chosen
+//! byte order, so that the same block can be shared with code which uses either
+//! convention. The byte order and the length are public; the bytes are secret.
+
+use super::subtle::{Choice, ConstantTimeEq, ConstantTimeLess};
+
+/// The byte order used when interpreting bytes as an unsigned integer.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Endianness {
+    /// The first byte is the least significant
+    Little,
+    /// The first byte is the most significant
+    Big,
+}
+
+impl Endianness {
+    /// Get the position of the byte with a given significance (0 is the least
+    /// significant byte) in a byte string of a given length.
+    #[inline]
+    fn position(self, significance: usize, len: usize) -> usize {
+        match self {
+            Endianness::Little => significance,
+            Endianness::Big => len - 1 - significance,
+        }
+    }
+}
+
+/// Test if the integer represented by lhs is less than the integer represented
+/// by rhs, in constant time.
+///
+/// Panics if lhs and rhs have different lengths.
+#[inline]
+pub fn ct_lt_bytes(lhs: &[u8], rhs: &[u8], endianness: Endianness) -> Choice {
+    assert_eq!(lhs.len(), rhs.len(), "lengths must match");
+    let len = lhs.len();
+    let mut result = Choice::from(0);
+    // Go from least to most significant byte, so that the most significant
+    // byte which differs decides the result.
+    for significance in 0..len {
+        let pos = endianness.position(significance, len);
+        let (a, b) = (lhs[pos], rhs[pos]);
+        result = a.ct_lt(&b) | (a.ct_eq(&b) & result);
+    }
+    result
+}
+
+/// Add one to the integer represented by bytes, in constant time, wrapping
+/// around on overflow.
+///
+/// Returns true if the addition overflowed (the bytes were all 0xff before).
+#[inline]
+pub fn ct_increment_bytes(bytes: &mut [u8], endianness: Endianness) -> Choice {
+    let len = bytes.len();
+    let mut carry = 1u16;
+    for significance in 0..len {
+        let pos = endianness.position(significance, len);
+        let sum = bytes[pos] as u16 + carry;
+        bytes[pos] = sum as u8;
+        carry = sum >> 8;
+    }
+    Choice::from(carry as u8)
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    // The same byte pattern compares differently under each byte order
+    #[test]
+    fn test_ct_lt_bytes_endianness() {
+        let a = [1u8, 0, 0, 0];
+        let b = [0u8, 0, 0, 1];
+        assert!(bool::from(ct_lt_bytes(&a, &b, Endianness::Little)));
+        assert!(!bool::from(ct_lt_bytes(&b, &a, Endianness::Little)));
+        assert!(!bool::from(ct_lt_bytes(&a, &b, Endianness::Big)));
+        assert!(bool::from(ct_lt_bytes(&b, &a, Endianness::Big)));
+
+        // Equal values are not less than each other
+        assert!(!bool::from(ct_lt_bytes(&a, &a, Endianness::Little)));
+        assert!(!bool::from(ct_lt_bytes(&a, &a, Endianness::Big)));
+
+        // Agrees with the integer comparison
+        for (x, y) in [(0u32, 1u32), (255, 256), (0x0102_0304, 0x0403_0201), (7, 7)].iter() {
+            assert_eq!(
+                bool::from(ct_lt_bytes(
+                    &x.to_le_bytes(),
+                    &y.to_le_bytes(),
+                    Endianness::Little
+                )),
+                x < y
+            );
+            assert_eq!(
+                bool::from(ct_lt_bytes(
+                    &x.to_be_bytes(),
+                    &y.to_be_bytes(),
+                    Endianness::Big
+                )),
+                x < y
+            );
+        }
+    }
+
+    // Incrementing carries across bytes in the chosen order, and wraps around
+    #[test]
+    fn test_ct_increment_bytes() {
+        let mut le = 255u32.to_le_bytes();
+        assert!(!bool::from(ct_increment_bytes(&mut le, Endianness::Little)));
+        assert_eq!(u32::from_le_bytes(le), 256);
+
+        let mut be = 255u32.to_be_bytes();
+        assert!(!bool::from(ct_increment_bytes(&mut be, Endianness::Big)));
+        assert_eq!(u32::from_be_bytes(be), 256);
+
+        let mut max = [0xffu8; 4];
+        assert!(bool::from(ct_increment_bytes(&mut max, Endianness::Big)));
+        assert_eq!(max, [0u8; 4]);
+    }
+}
@@ -5,7 +5,7 @@
 
 pub use aligned_array::{subtle, Aligned, AsAlignedChunks, AsNeSlice, A64, A8};
 pub use generic_array::{arr, typenum, ArrayLength, GenericArray};
-use subtle::Choice;
+use subtle::{Choice, ConstantTimeLess};
 
 /// An alias representing 8-byte aligned bytes, mainly to save typing
 pub type A8Bytes<N> = Aligned<A8, GenericArray<u8, N>>;
@@ -31,6 +31,16 @@ pub type A64Bytes<N> = Aligned<A64, GenericArray<u8, N>>;
 /// or put it in an ORAM.
 pub trait CMov: Sized {
     fn cmov(&mut self, condition: Choice, src: &Self);
+
+    /// Conditionally clear self, setting it to the default (zero) value.
+    /// The memory access pattern is the same whether or not condition is set.
+    #[inline]
+    fn ct_clear(&mut self, condition: Choice)
+    where
+        Self: Default,
+    {
+        self.cmov(condition, &Self::default())
+    }
 }
 
 impl CMov for u32 {
@@ -70,6 +80,49 @@ impl<N: ArrayLength<u8>> CMov for A64Bytes<N> {
     }
 }
 
+impl<const N: usize> CMov for [u8; N] {
+    #[inline]
+    fn cmov(&mut self, condition: Choice, src: &[u8; N]) {
+        // Byte-wise masked move: mask is 0xff if condition is true, else 0.
+        // This avoids branching on condition, and doesn't require alignment.
+        let mask = 0u8.wrapping_sub(condition.unwrap_u8());
+        for (dest, src) in self.iter_mut().zip(src.iter()) {
+            *dest ^= mask & (*dest ^ *src);
+        }
+    }
+}
+
+/// Constant-time conditional subtraction with a floor, for integer types, e.g.
+/// for decrementing a reference count which is stored in a value.
+pub trait CtSubFloored: CMov + Copy {
+    /// If condition is set, subtract delta from self, but not below floor:
+    /// `if condition { *self = max(*self - delta, floor) }`.
+    /// If self is already below floor, it is left as is.
+    ///
+    /// The memory access pattern and the timing are the same regardless of
+    /// the condition and the values.
+    fn ct_sub_assign_floored(&mut self, condition: Choice, delta: Self, floor: Self);
+}
+
+macro_rules! impl_ct_sub_floored {
+    ($($uint:ty),*) => {
+        $(
+            impl CtSubFloored for $uint {
+                #[inline]
+                fn ct_sub_assign_floored(&mut self, condition: Choice, delta: Self, floor: Self) {
+                    let mut result = self.wrapping_sub(delta);
+                    let below_floor = self.ct_lt(&delta) | result.ct_lt(&floor);
+                    result.cmov(below_floor, &floor);
+                    result.cmov(self.ct_lt(&floor), self);
+                    self.cmov(condition, &result);
+                }
+            }
+        )*
+    }
+}
+
+impl_ct_sub_floored!(u32, u64);
+
 #[inline]
 pub fn cswap<T: CMov + Default>(condition: Choice, a: &mut T, b: &mut T) {
     let mut temp = T::default();
@@ -78,6 +131,9 @@ pub fn cswap<T: CMov + Default>(condition: Choice, a: &mut T, b: &mut T) {
     b.cmov(condition, &temp);
 }
 
+mod int_view;
+pub use int_view::{ct_increment_bytes, ct_lt_bytes, Endianness};
+
 #[cfg_attr(not(feature = "no_asm_insecure"), path = "cmov_impl_asm.rs")]
 #[cfg_attr(feature = "no_asm_insecure", path = "cmov_impl_no_asm.rs")]
 mod cmov_impl;
@@ -136,6 +192,111 @@ mod testing {
         assert_eq!(a, 0);
     }
 
+    // Exercise CMov for [u8; N], checking that every byte of the array is moved
+    fn exercise_cmov_u8_array<const N: usize>() {
+        let ctrue: Choice = Choice::from(1u8);
+        let cfalse: Choice = Choice::from(0u8);
+
+        let mut a = [0u8; N];
+        a.cmov(ctrue, &[1u8; N]);
+        assert_eq!(a, [1u8; N]);
+
+        a.cmov(cfalse, &[0u8; N]);
+        assert_eq!(a, [1u8; N]);
+
+        a.cmov(ctrue, &[0xffu8; N]);
+        assert_eq!(a, [0xffu8; N]);
+
+        a.cmov(cfalse, &[2u8; N]);
+        assert_eq!(a, [0xffu8; N]);
+
+        let mut pattern = [0u8; N];
+        for (idx, byte) in pattern.iter_mut().enumerate() {
+            *byte = (idx as u8).wrapping_mul(7).wrapping_add(3);
+        }
+
+        a.cmov(ctrue, &pattern);
+        assert_eq!(a, pattern);
+
+        a.cmov(cfalse, &[0u8; N]);
+        assert_eq!(a, pattern);
+
+        a.cmov(ctrue, &[0u8; N]);
+        assert_eq!(a, [0u8; N]);
+    }
+
+    #[test]
+    fn test_cmov_u8_array() {
+        exercise_cmov_u8_array::<0>();
+        exercise_cmov_u8_array::<1>();
+        exercise_cmov_u8_array::<7>();
+        exercise_cmov_u8_array::<16>();
+        exercise_cmov_u8_array::<32>();
+        exercise_cmov_u8_array::<33>();
+        exercise_cmov_u8_array::<64>();
+    }
+
+    #[test]
+    fn test_ct_clear() {
+        let ctrue: Choice = Choice::from(1u8);
+        let cfalse: Choice = Choice::from(0u8);
+
+        let mut a = 5u64;
+        a.ct_clear(cfalse);
+        assert_eq!(a, 5);
+        a.ct_clear(ctrue);
+        assert_eq!(a, 0);
+
+        let mut b: A8Bytes<U72> = to_a8_bytes(&[7u8; 72]);
+        b.ct_clear(cfalse);
+        assert_eq!(*b, *to_a8_bytes(&[7u8; 72]));
+        b.ct_clear(ctrue);
+        assert_eq!(*b, *to_a8_bytes(&[0u8; 72]));
+
+        let mut c: A64Bytes<U128> = to_a64_bytes(&[9u8; 128]);
+        c.ct_clear(cfalse);
+        assert_eq!(*c, *to_a64_bytes(&[9u8; 128]));
+        c.ct_clear(ctrue);
+        assert_eq!(*c, *to_a64_bytes(&[0u8; 128]));
+    }
+
+    #[test]
+    fn test_ct_sub_assign_floored() {
+        let ctrue: Choice = Choice::from(1u8);
+        let cfalse: Choice = Choice::from(0u8);
+
+        // Normal decrement
+        let mut a = 10u64;
+        a.ct_sub_assign_floored(ctrue, 3, 0);
+        assert_eq!(a, 7);
+        a.ct_sub_assign_floored(ctrue, 5, 2);
+        assert_eq!(a, 2);
+
+        // Clamp at the floor, including when the subtraction would wrap
+        let mut b = 5u32;
+        b.ct_sub_assign_floored(ctrue, 4, 3);
+        assert_eq!(b, 3);
+        b.ct_sub_assign_floored(ctrue, 10, 1);
+        assert_eq!(b, 1);
+        b.ct_sub_assign_floored(ctrue, u32::MAX, 0);
+        assert_eq!(b, 0);
+        let mut c = u64::MAX;
+        c.ct_sub_assign_floored(ctrue, u64::MAX, 0);
+        assert_eq!(c, 0);
+
+        // Never raised to the floor
+        let mut d = 2u64;
+        d.ct_sub_assign_floored(ctrue, 1, 5);
+        assert_eq!(d, 2);
+
+        // False condition is a no-op
+        let mut e = 10u32;
+        e.ct_sub_assign_floored(cfalse, 3, 0);
+        assert_eq!(e, 10);
+        e.ct_sub_assign_floored(cfalse, 20, 15);
+        assert_eq!(e, 10);
+    }
+
     #[test]
     fn test_cmov_64bytes() {
         let ctrue: Choice = Choice::from(1u8);
@@ -165,9 +165,31 @@ macro_rules! implement_tree_index_for_primitive {
 implement_tree_index_for_primitive!(u32);
 implement_tree_index_for_primitive!(u64);
 
+/// Plaintext reference implementations of some of the `TreeIndex` operations.
+///
+/// These are NOT constant-time, and are only meant for cross-checking the
+/// constant-time implementations in tests.
+pub mod reference {
+    use super::TreeIndex;
+
+    /// Naive implementation of common_ancestor_distance_of_peers, which walks
+    /// up from both nodes until the paths meet.
+    pub fn common_ancestor_distance_of_peers<I: TreeIndex>(lhs: &I, rhs: &I) -> u32 {
+        let mut counter = 0u32;
+        let mut it1 = lhs.parents();
+        let mut it2 = rhs.parents();
+        while it1.next().unwrap() != it2.next().unwrap() {
+            counter += 1;
+        }
+        counter
+    }
+}
+
 #[cfg(test)]
 mod testing {
-    use super::*;
+    use super::{
+        reference::common_ancestor_distance_of_peers as naive_common_ancestor_distance_of_peers, *,
+    };
     extern crate alloc;
     use alloc::vec;
 
@@ -418,17 +440,6 @@ mod testing {
         assert_eq!(17u32.common_ancestor_distance_of_peers(&19u32), 2);
     }
 
-    // Naive implementation of common_ancestor_distance_of_peers
-    fn naive_common_ancestor_distance_of_peers<I: TreeIndex>(lhs: &I, rhs: &I) -> u32 {
-        let mut counter = 0u32;
-        let mut it1 = lhs.parents();
-        let mut it2 = rhs.parents();
-        while it1.next().unwrap() != it2.next().unwrap() {
-            counter = counter + 1;
-        }
-        counter
-    }
-
     // Test that common_ancestor_distance_of_peers agrees with the naive implementation
     #[test]
     fn common_ancestor_distance_conformance_u64() {
@@ -485,6 +496,36 @@ mod testing {
         })
     }
 
+    // Test common_ancestor_distance_of_peers against the reference implementation,
+    // using the verify_oblivious harness
+    #[test]
+    fn common_ancestor_distance_verify_oblivious() {
+        for ht in 0..30 {
+            test_helper::verify_oblivious(
+                10,
+                |rng| {
+                    (
+                        1u64.random_child_at_height(ht, rng),
+                        1u64.random_child_at_height(ht, rng),
+                    )
+                },
+                |(lhs, rhs)| lhs.common_ancestor_distance_of_peers(rhs),
+                |(lhs, rhs)| naive_common_ancestor_distance_of_peers(lhs, rhs),
+            );
+            test_helper::verify_oblivious(
+                10,
+                |rng| {
+                    (
+                        1u32.random_child_at_height(ht, rng),
+                        1u32.random_child_at_height(ht, rng),
+                    )
+                },
+                |(lhs, rhs)| lhs.common_ancestor_distance_of_peers(rhs),
+                |(lhs, rhs)| naive_common_ancestor_distance_of_peers(lhs, rhs),
+            );
+        }
+    }
+
     // Test that common_ancestor_height is giving expected results for nodes
     // at different heights.
     #[test]
@@ -542,4 +583,60 @@ mod testing {
         assert_eq!(17u32.common_ancestor_height(&63u32), 0);
         assert_eq!(17u32.common_ancestor_height(&127u32), 0);
     }
+
+    // Test that the tree arithmetic doesn't overflow for leaves at the largest
+    // height that fits in the index type.
+    #[test]
+    fn test_max_height_u64() {
+        const MAX_HT: u32 = 63;
+        test_helper::run_with_several_seeds(|mut rng| {
+            for _ in 0..100 {
+                let lhs = 1u64.random_child_at_height(MAX_HT, &mut rng);
+                let rhs = 1u64.random_child_at_height(MAX_HT, &mut rng);
+                assert_eq!(lhs.height(), MAX_HT);
+                assert_eq!(lhs.parents().count(), MAX_HT as usize + 1);
+                assert_eq!(lhs.parent(MAX_HT), 1u64);
+                assert_eq!(
+                    lhs.common_ancestor_distance_of_peers(&rhs),
+                    naive_common_ancestor_distance_of_peers(&lhs, &rhs)
+                );
+                assert_eq!(lhs.common_ancestor_height(&1u64), 0);
+                assert_eq!(lhs.common_ancestor_height(&lhs), MAX_HT);
+            }
+            assert_eq!(u64::MAX.height(), MAX_HT);
+            assert_eq!((1u64 << MAX_HT).height(), MAX_HT);
+            assert_eq!(
+                u64::MAX.common_ancestor_distance_of_peers(&(1u64 << MAX_HT)),
+                MAX_HT
+            );
+        })
+    }
+
+    // Test that the tree arithmetic doesn't overflow for leaves at the largest
+    // height that fits in the index type.
+    #[test]
+    fn test_max_height_u32() {
+        const MAX_HT: u32 = 31;
+        test_helper::run_with_several_seeds(|mut rng| {
+            for _ in 0..100 {
+                let lhs = 1u32.random_child_at_height(MAX_HT, &mut rng);
+                let rhs = 1u32.random_child_at_height(MAX_HT, &mut rng);
+                assert_eq!(lhs.height(), MAX_HT);
+                assert_eq!(lhs.parents().count(), MAX_HT as usize + 1);
+                assert_eq!(lhs.parent(MAX_HT), 1u32);
+                assert_eq!(
+                    lhs.common_ancestor_distance_of_peers(&rhs),
+                    naive_common_ancestor_distance_of_peers(&lhs, &rhs)
+                );
+                assert_eq!(lhs.common_ancestor_height(&1u32), 0);
+                assert_eq!(lhs.common_ancestor_height(&lhs), MAX_HT);
+            }
+            assert_eq!(u32::MAX.height(), MAX_HT);
+            assert_eq!((1u32 << MAX_HT).height(), MAX_HT);
+            assert_eq!(
+                u32::MAX.common_ancestor_distance_of_peers(&(1u32 << MAX_HT)),
+                MAX_HT
+            );
+        })
+    }
 }
@@ -0,0 +1,211 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! An oblivious Bloom filter, where the bit array is an oblivious RAM.
+//!
+//! Each ORAM block holds BlockSize * 8 bits of the filter. A key is hashed by
+//! each of several keyed hash functions to a bit of the filter, which selects
+//! a block, and a bit within it. Inserting a key sets all of its bits, and
+//! a query checks that all of them are set. Both touch exactly one block per
+//! hash function, and scan the whole block to get at the bit, so the access
+//! pattern reveals neither the key nor the result.
+//!
+//! As with any Bloom filter, there are no false negatives, but there are
+//! false positives, at a rate which depends on the number of bits per key and
+//! the number of hash functions.
+
+use super::SipBuildHasher;
+use aligned_cmov::{
+    subtle::{Choice, ConditionallySelectable, ConstantTimeEq},
+    A64Bytes, A8Bytes, ArrayLength,
+};
+use alloc::vec::Vec;
+use core::{
+    hash::{BuildHasher, Hasher},
+    marker::PhantomData,
+};
+use mc_oblivious_traits::{log2_ceil, ORAMCreator, ORAM};
+use rand_core::{CryptoRng, RngCore};
+
+/// An oblivious Bloom filter over keys of KeySize bytes, backed by an ORAM
+/// with blocks of BlockSize bytes.
+pub struct ObliviousBloomFilter<KeySize, BlockSize, O>
+where
+    KeySize: ArrayLength<u8>,
+    BlockSize: ArrayLength<u8>,
+    O: ORAM<BlockSize> + Send + Sync + 'static,
+{
+    /// The number of blocks of the filter, a power of two
+    num_blocks: u64,
+    /// Keys for each of the hash functions
+    hashers: Vec<SipBuildHasher>,
+    /// Oblivious storage for the bits of the filter
+    oram: O,
+    // phantom data
+    _key_size: PhantomData<fn() -> KeySize>,
+    _block_size: PhantomData<fn() -> BlockSize>,
+}
+
+impl<KeySize, BlockSize, O> ObliviousBloomFilter<KeySize, BlockSize, O>
+where
+    KeySize: ArrayLength<u8>,
+    BlockSize: ArrayLength<u8>,
+    O: ORAM<BlockSize> + Send + Sync + 'static,
+{
+    /// The number of bits in one block
+    const BLOCK_BITS: u64 = BlockSize::U64 * 8;
+
+    /// Create a new, empty filter, with at least the desired number of bits,
+    /// and the given number of hash functions. The block size must be a power
+    /// of two.
+    pub fn new<OC, RngType, M>(
+        desired_bits: u64,
+        num_hashes: usize,
+        stash_size: usize,
+        mut maker: M,
+    ) -> Self
+    where
+        OC: ORAMCreator<BlockSize, RngType, Output = O>,
+        RngType: RngCore + CryptoRng,
+        M: 'static + FnMut() -> RngType,
+    {
+        assert!(
+            BlockSize::U64.is_power_of_two(),
+            "block size must be a power of two"
+        );
+        assert!(num_hashes > 0, "there must be at least one hash function");
+        let num_bits = (1u64 << log2_ceil(desired_bits)).max(Self::BLOCK_BITS);
+        let num_blocks = num_bits / Self::BLOCK_BITS;
+        let oram = OC::create(num_blocks, stash_size, &mut maker);
+        debug_assert!(num_blocks <= oram.len(), "unexpected oram capacity");
+
+        let mut rng = maker();
+        let hashers = (0..num_hashes)
+            .map(|_| SipBuildHasher::from_rng(&mut rng))
+            .collect();
+        Self {
+            num_blocks,
+            hashers,
+            oram,
+            _key_size: Default::default(),
+            _block_size: Default::default(),
+        }
+    }
+
+    /// The number of bits in the filter
+    pub fn num_bits(&self) -> u64 {
+        self.num_blocks * Self::BLOCK_BITS
+    }
+
+    /// The number of hash functions, which is the number of ORAM accesses for
+    /// each insert or query
+    pub fn num_hashes(&self) -> usize {
+        self.hashers.len()
+    }
+
+    /// Insert a key into the filter
+    pub fn insert(&mut self, key: &A8Bytes<KeySize>) {
+        for idx in 0..self.hashers.len() {
+            let (block_num, byte_num, bit) = self.hash_key(idx, key);
+            self.oram.access(block_num, |block| {
+                for (num, byte) in block.iter_mut().enumerate() {
+                    let new_val = *byte | bit;
+                    byte.conditional_assign(&new_val, (num as u64).ct_eq(&byte_num));
+                }
+            });
+        }
+    }
+
+    /// Test if a key may have been inserted into the filter.
+    /// If this is false, the key was certainly never inserted.
+    pub fn maybe_contains(&mut self, key: &A8Bytes<KeySize>) -> Choice {
+        let mut result = Choice::from(1);
+        for idx in 0..self.hashers.len() {
+            let (block_num, byte_num, bit) = self.hash_key(idx, key);
+            let is_set = self
+                .oram
+                .access_ref(block_num, |block| Self::ct_test_bit(block, byte_num, bit));
+            result &= is_set;
+        }
+        result
+    }
+
+    /// Test if a bit is set in a block, scanning every byte of it
+    fn ct_test_bit(block: &A64Bytes<BlockSize>, byte_num: u64, bit: u8) -> Choice {
+        let mut result = Choice::from(0);
+        for (num, byte) in block.iter().enumerate() {
+            result |= (num as u64).ct_eq(&byte_num) & !(*byte & bit).ct_eq(&0);
+        }
+        result
+    }
+
+    /// Hash a key with one of the hash functions, to a block num, a byte
+    /// within the block, and a mask for the bit within the byte
+    fn hash_key(&self, idx: usize, key: &A8Bytes<KeySize>) -> (u64, u64, u8) {
+        let mut hasher = self.hashers[idx].build_hasher();
+        hasher.write(key.as_slice());
+        let hash = hasher.finish();
+        let bit_num = hash & (Self::BLOCK_BITS - 1);
+        let block_num = (hash >> log2_ceil(Self::BLOCK_BITS)) & (self.num_blocks - 1);
+        (block_num, bit_num >> 3, 1u8 << (bit_num & 7))
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use aligned_cmov::typenum::{U1024, U8};
+    use mc_oblivious_ram::PathORAM4096Z4Creator;
+    use mc_oblivious_traits::{rng_maker, HeapORAMStorageCreator};
+    use test_helper::{run_with_several_seeds, RngType};
+
+    type ORAMCreatorZ4 = PathORAM4096Z4Creator<RngType, HeapORAMStorageCreator>;
+    type TestFilter =
+        ObliviousBloomFilter<U8, U1024, <ORAMCreatorZ4 as ORAMCreator<U1024, RngType>>::Output>;
+
+    fn key(num: u64) -> A8Bytes<U8> {
+        let mut result = A8Bytes::<U8>::default();
+        result.copy_from_slice(&num.to_le_bytes());
+        result
+    }
+
+    // Test that inserted keys are always found, and that absent keys are found
+    // at about the expected false positive rate
+    #[test]
+    fn no_false_negatives() {
+        run_with_several_seeds(|rng| {
+            let mut filter = TestFilter::new::<ORAMCreatorZ4, _, _>(8192, 4, 16, rng_maker(rng));
+            assert_eq!(filter.num_bits(), 8192);
+            assert_eq!(filter.num_hashes(), 4);
+
+            for num in 0..1000 {
+                filter.insert(&key(num));
+            }
+            for num in 0..1000 {
+                assert!(bool::from(filter.maybe_contains(&key(num))));
+            }
+
+            // With 8 bits per key and 4 hash functions, the false positive rate
+            // is about (1 - e^(-1/2))^4, or 2.4%
+            let false_positives = (1000..6000)
+                .filter(|num| bool::from(filter.maybe_contains(&key(*num))))
+                .count();
+            assert!(
+                false_positives < 250,
+                "too many false positives: {}",
+                false_positives
+            );
+        });
+    }
+
+    // Test that an empty filter contains nothing
+    #[test]
+    fn empty_filter() {
+        run_with_several_seeds(|rng| {
+            let mut filter = TestFilter::new::<ORAMCreatorZ4, _, _>(1, 3, 16, rng_maker(rng));
+            assert_eq!(filter.num_bits(), 8192);
+            for num in 0..100 {
+                assert!(!bool::from(filter.maybe_contains(&key(num))));
+            }
+        });
+    }
+}
@@ -29,6 +29,9 @@ use typenum::{PartialDiv, Sum, U8};
 mod build_hasher;
 use build_hasher::SipBuildHasher;
 
+mod bloom_filter;
+pub use bloom_filter::ObliviousBloomFilter;
+
 /// In this implementation, the cuckoo hashing step is permitted to repeat at most 6 times
 /// before we give up. In experiments this lead to about ~75% memory utilitzation.
 /// This will depend on a lot of factors such as how big is the block size relative
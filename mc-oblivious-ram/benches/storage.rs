@@ -0,0 +1,102 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! Compare PathORAM access latency across storage backends.
+//!
+//! Each backend is built with the same ORAM creator, size and stash size, so
+//! only the storage differs. Besides criterion's own statistics, this prints
+//! latency percentiles for single accesses, since tail latency is often what
+//! matters when choosing a backend.
+//!
+//! The ORAM has 64Ki blocks by default, about 128 MiB of storage. A larger
+//! size, which must be a power of two, can be set with ORAM_BENCH_SIZE, e.g.
+//! `ORAM_BENCH_SIZE=1048576 cargo bench --bench storage` for about 2 GiB.
+
+use aligned_cmov::{
+    typenum::{U1024, U4096, U64},
+    A64Bytes,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use mc_oblivious_ram::PathORAM4096Z4Creator;
+use mc_oblivious_traits::{
+    rng_maker, HeapORAMStorageCreator, ORAMCreator, ORAMStorageCreator, RngCore, ORAM,
+};
+use std::time::{Duration, Instant};
+use test_helper::{get_seeded_rng, RngType};
+
+const DEFAULT_SIZE: u64 = 64 * 1024;
+const SIZE_ENV_VAR: &str = "ORAM_BENCH_SIZE";
+const STASH_SIZE: usize = 32;
+const NUM_SAMPLES: usize = 10_000;
+
+/// The number of blocks in the benchmarked ORAM
+fn oram_size() -> u64 {
+    match std::env::var(SIZE_ENV_VAR) {
+        Ok(size) => {
+            let size: u64 = size
+                .parse()
+                .unwrap_or_else(|_| panic!("{} must be a number, got {:?}", SIZE_ENV_VAR, size));
+            assert!(
+                size.is_power_of_two(),
+                "{} must be a power of two",
+                SIZE_ENV_VAR
+            );
+            size
+        }
+        Err(_) => DEFAULT_SIZE,
+    }
+}
+
+/// Time single accesses at random keys, and print latency percentiles
+fn report_percentiles<O: ORAM<U1024>>(name: &str, oram: &mut O, rng: &mut RngType) {
+    let size = oram.len();
+    let mut samples: Vec<Duration> = (0..NUM_SAMPLES)
+        .map(|_| {
+            let key = rng.next_u64() % size;
+            let start = Instant::now();
+            oram.access(key, |val| black_box(val[0]));
+            start.elapsed()
+        })
+        .collect();
+    samples.sort_unstable();
+    let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+    println!(
+        "{}: p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+        name,
+        percentile(50),
+        percentile(90),
+        percentile(99),
+        percentile(100)
+    );
+}
+
+/// Benchmark PathORAM on one storage backend
+fn bench_backend<SC: ORAMStorageCreator<U4096, U64>>(c: &mut Criterion, name: &str) {
+    let mut maker = rng_maker(get_seeded_rng());
+    let mut rng = maker();
+    let size = oram_size();
+    let mut oram = PathORAM4096Z4Creator::<RngType, SC>::create(size, STASH_SIZE, &mut maker);
+    let val = A64Bytes::default();
+    for key in 0..size {
+        oram.write(key, &val);
+    }
+
+    report_percentiles(name, &mut oram, &mut rng);
+
+    c.bench_function(&format!("{} storage, random access", name), |b| {
+        b.iter(|| {
+            let key = rng.next_u64() % size;
+            oram.access(key, |val| black_box(val[0]))
+        })
+    });
+}
+
+pub fn heap_storage(c: &mut Criterion) {
+    bench_backend::<HeapORAMStorageCreator>(c, "heap");
+}
+
+criterion_group! {
+    name = storage;
+    config = Criterion::default().measurement_time(Duration::new(10, 0));
+    targets = heap_storage
+}
+criterion_main!(storage);
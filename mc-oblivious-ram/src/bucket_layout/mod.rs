@@ -0,0 +1,182 @@
+//! Conversion between the buckets of a PathORAM and a flat byte image, with a
+//! configurable layout, for exchanging ORAM images with other tools.
+//!
+//! In storage, a bucket of Z slots is two buffers: the Z values, one after
+//! another, and the Z metadata, one after another. An image of the bucket is
+//! one buffer of bytes, and a `BucketLayout` says where each value and each
+//! metadata is within it. `SplitLayout` matches the storage: all of the values,
+//! followed by all of the metadata.
+//!
+//! The offsets only depend on the sizes and the slot, and not on the contents,
+//! so converting a bucket is constant time, but an image is not encrypted, and
+//! these are meant for non-secret tooling, e.g. import and export.
+
+use crate::path_oram::MetaSize;
+use aligned_cmov::{
+    typenum::{Prod, Unsigned},
+    A64Bytes, A8Bytes, ArrayLength,
+};
+use core::ops::Mul;
+
+/// The placement of the values and metadata of a bucket within its image.
+///
+/// Each of the Z values is `value_size` bytes, and each metadata is
+/// `MetaSize` bytes. The value and metadata ranges of all of the slots must be
+/// disjoint, and within `bucket_len`.
+pub trait BucketLayout {
+    /// The number of bytes in the image of a bucket
+    fn bucket_len(value_size: usize, z: usize) -> usize;
+    /// The offset in the image of the value of a slot
+    fn value_offset(value_size: usize, z: usize, slot: usize) -> usize;
+    /// The offset in the image of the metadata of a slot
+    fn meta_offset(value_size: usize, z: usize, slot: usize) -> usize;
+}
+
+/// The layout matching the storage: the values of the bucket, in slot order,
+/// followed by the metadata, in slot order, with no padding.
+pub struct SplitLayout;
+
+impl BucketLayout for SplitLayout {
+    fn bucket_len(value_size: usize, z: usize) -> usize {
+        z * (value_size + MetaSize::USIZE)
+    }
+    fn value_offset(value_size: usize, _z: usize, slot: usize) -> usize {
+        slot * value_size
+    }
+    fn meta_offset(value_size: usize, z: usize, slot: usize) -> usize {
+        z * value_size + slot * MetaSize::USIZE
+    }
+}
+
+/// Write the image of a bucket, in the layout L, to dest.
+///
+/// Bytes of dest which are not part of any value or metadata are unchanged.
+/// Panics if dest is not exactly `L::bucket_len` bytes.
+pub fn write_bucket<L, ValueSize, Z>(
+    data: &A64Bytes<Prod<Z, ValueSize>>,
+    meta: &A8Bytes<Prod<Z, MetaSize>>,
+    dest: &mut [u8],
+) where
+    L: BucketLayout,
+    ValueSize: Unsigned,
+    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
+    Prod<Z, ValueSize>: ArrayLength<u8>,
+    Prod<Z, MetaSize>: ArrayLength<u8>,
+{
+    let (value_size, meta_size, z) = (ValueSize::USIZE, MetaSize::USIZE, Z::USIZE);
+    assert_eq!(dest.len(), L::bucket_len(value_size, z), "wrong image size");
+    for slot in 0..z {
+        let offset = L::value_offset(value_size, z, slot);
+        dest[offset..offset + value_size]
+            .copy_from_slice(&data[slot * value_size..(slot + 1) * value_size]);
+        let offset = L::meta_offset(value_size, z, slot);
+        dest[offset..offset + meta_size]
+            .copy_from_slice(&meta[slot * meta_size..(slot + 1) * meta_size]);
+    }
+}
+
+/// Read a bucket from its image, in the layout L, from src.
+///
+/// Panics if src is not exactly `L::bucket_len` bytes.
+pub fn read_bucket<L, ValueSize, Z>(
+    src: &[u8],
+    data: &mut A64Bytes<Prod<Z, ValueSize>>,
+    meta: &mut A8Bytes<Prod<Z, MetaSize>>,
+) where
+    L: BucketLayout,
+    ValueSize: Unsigned,
+    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
+    Prod<Z, ValueSize>: ArrayLength<u8>,
+    Prod<Z, MetaSize>: ArrayLength<u8>,
+{
+    let (value_size, meta_size, z) = (ValueSize::USIZE, MetaSize::USIZE, Z::USIZE);
+    assert_eq!(src.len(), L::bucket_len(value_size, z), "wrong image size");
+    for slot in 0..z {
+        let offset = L::value_offset(value_size, z, slot);
+        data[slot * value_size..(slot + 1) * value_size]
+            .copy_from_slice(&src[offset..offset + value_size]);
+        let offset = L::meta_offset(value_size, z, slot);
+        meta[slot * meta_size..(slot + 1) * meta_size]
+            .copy_from_slice(&src[offset..offset + meta_size]);
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use aligned_cmov::typenum::{U128, U4};
+    use alloc::vec;
+
+    type Data = A64Bytes<Prod<U4, U128>>;
+    type Meta = A8Bytes<Prod<U4, MetaSize>>;
+
+    // Each slot is its metadata followed by its value, and the image has a
+    // header of 8 bytes, which is not part of any slot
+    struct InterleavedLayout;
+
+    impl BucketLayout for InterleavedLayout {
+        fn bucket_len(value_size: usize, z: usize) -> usize {
+            8 + z * (value_size + MetaSize::USIZE)
+        }
+        fn value_offset(value_size: usize, _z: usize, slot: usize) -> usize {
+            8 + slot * (value_size + MetaSize::USIZE) + MetaSize::USIZE
+        }
+        fn meta_offset(value_size: usize, _z: usize, slot: usize) -> usize {
+            8 + slot * (value_size + MetaSize::USIZE)
+        }
+    }
+
+    fn test_bucket() -> (Data, Meta) {
+        let mut data = Data::default();
+        for (idx, byte) in data.iter_mut().enumerate() {
+            *byte = idx as u8;
+        }
+        let mut meta = Meta::default();
+        for (idx, byte) in meta.iter_mut().enumerate() {
+            *byte = 0x80 | idx as u8;
+        }
+        (data, meta)
+    }
+
+    // The split layout is the data buffer followed by the metadata buffer
+    #[test]
+    fn split_layout_matches_storage() {
+        let (data, meta) = test_bucket();
+        let mut image = vec![0u8; SplitLayout::bucket_len(128, 4)];
+        write_bucket::<SplitLayout, U128, U4>(&data, &meta, &mut image);
+        assert_eq!(&image[..512], data.as_slice());
+        assert_eq!(&image[512..], meta.as_slice());
+    }
+
+    // Round trip a bucket through an alternate layout, and convert its image
+    // to the split layout
+    #[test]
+    fn interleaved_layout_round_trip() {
+        let (data, meta) = test_bucket();
+        let mut image = vec![0xffu8; InterleavedLayout::bucket_len(128, 4)];
+        write_bucket::<InterleavedLayout, U128, U4>(&data, &meta, &mut image);
+        assert_eq!(image[..8], [0xff; 8]);
+        assert_eq!(&image[8..24], &meta[..16]);
+        assert_eq!(&image[24..152], &data[..128]);
+        assert_eq!(&image[152..168], &meta[16..32]);
+
+        let mut read_data = Data::default();
+        let mut read_meta = Meta::default();
+        read_bucket::<InterleavedLayout, U128, U4>(&image, &mut read_data, &mut read_meta);
+        assert_eq!(read_data, data);
+        assert_eq!(read_meta, meta);
+
+        let mut split_image = vec![0u8; SplitLayout::bucket_len(128, 4)];
+        write_bucket::<SplitLayout, U128, U4>(&read_data, &read_meta, &mut split_image);
+        assert_eq!(&split_image[..512], data.as_slice());
+        assert_eq!(&split_image[512..], meta.as_slice());
+    }
+
+    // An image of the wrong size for the layout is rejected
+    #[test]
+    #[should_panic(expected = "wrong image size")]
+    fn wrong_image_size() {
+        let (mut data, mut meta) = test_bucket();
+        read_bucket::<SplitLayout, U128, U4>(&[0u8; 100], &mut data, &mut meta);
+    }
+}
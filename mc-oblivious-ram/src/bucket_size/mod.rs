@@ -0,0 +1,161 @@
+//! Host-side helpers for choosing the bucket size (Z) of a PathORAM.
+//!
+//! The model used here is simple: if blocks are assigned uniformly at random
+//! to leaves, the number of blocks assigned to a given leaf is approximately
+//! Poisson distributed, with mean equal to the load factor (blocks per leaf).
+//! We recommend the smallest Z such that a bucket receiving that many blocks
+//! overflows with at most the target probability.
+//!
+//! This is a heuristic to guide configuration, not a proof of a stash bound.
+//! For a load factor of 1 it agrees with the Path ORAM paper, which finds
+//! Z = 4 sufficient in practice and proves a bound for Z >= 5.
+
+/// The largest load factor accepted by `recommend_z`
+pub const MAX_LOAD_FACTOR: f64 = 64.0;
+
+/// Recommend the smallest bucket size Z for a PathORAM with the given load
+/// factor (expected number of blocks per leaf), such that the probability
+/// of a bucket overflowing is at most `target_prob`.
+///
+/// Panics if the load factor is not in (0, MAX_LOAD_FACTOR], or if the
+/// target probability is not in (0, 1).
+pub fn recommend_z(load_factor: f64, target_prob: f64) -> u32 {
+    assert!(
+        load_factor > 0.0 && load_factor <= MAX_LOAD_FACTOR,
+        "load factor must be in (0, {}]",
+        MAX_LOAD_FACTOR
+    );
+    assert!(
+        target_prob > 0.0 && target_prob < 1.0,
+        "target probability must be in (0, 1)"
+    );
+    let mut z = 0u32;
+    while poisson_tail(load_factor, z) > target_prob {
+        z += 1;
+    }
+    z
+}
+
+// Compute P[X > z] where X is Poisson distributed with mean lambda.
+// The tail is summed directly, rather than computed as 1 - P[X <= z],
+// so that very small target probabilities don't get lost to rounding.
+fn poisson_tail(lambda: f64, z: u32) -> f64 {
+    let mut k = z as u64 + 1;
+    let mut pmf = exp_neg(lambda);
+    for i in 1..=k {
+        pmf *= lambda / i as f64;
+    }
+    let mut result = 0.0;
+    loop {
+        result += pmf;
+        k += 1;
+        pmf *= lambda / k as f64;
+        if k as f64 > lambda && pmf <= result * f64::EPSILON {
+            return result;
+        }
+    }
+}
+
+// Compute e^{-x} for x >= 0, since core doesn't provide exp.
+// The argument is halved until it is small, the Taylor series is used,
+// and then the result is squared back up.
+fn exp_neg(x: f64) -> f64 {
+    debug_assert!(x >= 0.0);
+    let mut reduced = x;
+    let mut num_squarings = 0;
+    while reduced > 0.5 {
+        reduced /= 2.0;
+        num_squarings += 1;
+    }
+    let mut result = 1.0;
+    let mut term = 1.0;
+    for n in 1..20 {
+        term *= -reduced / n as f64;
+        result += term;
+    }
+    for _ in 0..num_squarings {
+        result *= result;
+    }
+    result
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    fn assert_close(lhs: f64, rhs: f64) {
+        assert!(
+            (lhs - rhs).abs() <= rhs * 1e-12,
+            "{} is not close to {}",
+            lhs,
+            rhs
+        );
+    }
+
+    #[test]
+    fn test_exp_neg() {
+        assert_eq!(exp_neg(0.0), 1.0);
+        assert_close(exp_neg(0.5), 0.6065306597126334);
+        assert_close(exp_neg(1.0), 0.36787944117144233);
+        assert_close(exp_neg(4.0), 0.01831563888873418);
+        assert_close(exp_neg(64.0), 1.603810890548638e-28);
+    }
+
+    #[test]
+    fn test_poisson_tail() {
+        // P[X > 0] = 1 - e^{-lambda}
+        assert_close(poisson_tail(1.0, 0), 1.0 - 0.36787944117144233);
+        // P[X > 4] for lambda = 1 is 1 - e^{-1} (1 + 1 + 1/2 + 1/6 + 1/24)
+        assert_close(poisson_tail(1.0, 4), 0.0036598468273437);
+    }
+
+    // At a load factor of 1, the Path ORAM paper uses Z = 4 in its experiments
+    // and proves a negligible stash overflow probability for Z = 5.
+    #[test]
+    fn recommend_z_matches_path_oram_paper() {
+        assert_eq!(recommend_z(1.0, 0.01), 4);
+        assert_eq!(recommend_z(1.0, 0.001), 5);
+        // Half as many blocks per leaf needs a smaller bucket
+        assert_eq!(recommend_z(0.5, 0.01), 3);
+        // Two blocks per leaf is the configuration that overflows with Z = 2
+        assert!(recommend_z(2.0, 0.01) > 2);
+    }
+
+    // The recommendation is the smallest Z which meets the target, for a range
+    // of load factors and targets
+    #[test]
+    fn recommend_z_is_smallest_sufficient() {
+        let loads = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, MAX_LOAD_FACTOR];
+        let targets = [0.1, 1e-3, 1e-6, 1e-12, 1e-40];
+        for load in loads.iter() {
+            for target in targets.iter() {
+                let z = recommend_z(*load, *target);
+                assert!(poisson_tail(*load, z) <= *target);
+                assert!(z == 0 || poisson_tail(*load, z - 1) > *target);
+            }
+        }
+    }
+
+    // More blocks per leaf, or a smaller target, never needs a smaller bucket
+    #[test]
+    fn recommend_z_is_monotone() {
+        let loads = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, MAX_LOAD_FACTOR];
+        let targets = [0.1, 1e-3, 1e-6, 1e-12, 1e-40];
+        for load in loads.iter() {
+            for pair in targets.windows(2) {
+                assert!(recommend_z(*load, pair[0]) <= recommend_z(*load, pair[1]));
+            }
+        }
+        for target in targets.iter() {
+            for pair in loads.windows(2) {
+                assert!(recommend_z(pair[0], *target) <= recommend_z(pair[1], *target));
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "load factor must be in")]
+    fn recommend_z_rejects_large_load_factor() {
+        recommend_z(MAX_LOAD_FACTOR * 2.0, 0.01);
+    }
+}
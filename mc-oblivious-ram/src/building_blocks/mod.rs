@@ -0,0 +1,58 @@
+//! Primitives of the PathORAM implementation, for building other oblivious
+//! data structures, such as custom evictors and containers, on top of them.
+//!
+//! A metadata (of size `MetaSize`) accompanies every value slot, in a bucket
+//! of the tree or in a stash. It holds two u64's:
+//! - The leaf num, a TreeIndex of a leaf of the tree, which is the branch the
+//!   value belongs to. A slot is vacant exactly when its leaf num is 0.
+//! - The block num, the user-facing key of the value. Its highest bit is the
+//!   pin bit, which is not part of the key, see `meta_is_pinned`. The block
+//!   num is set with `meta_set_block_num_if`, which keeps the pin bit.
+//!
+//! The invariants which must hold for the tree to be correct are:
+//! - Every non-vacant slot in a bucket is on the branch of its own leaf num
+//! - No block num appears in more than one non-vacant slot
+//!
+//! The constant time operations here preserve these invariants, as long as
+//! their inputs satisfy them. Reading or writing the metadata fields directly
+//! is not constant time with respect to their values, and is meant for
+//! building metadata, or for non-secret maintenance.
+//!
+//! ```
+//! use aligned_cmov::{
+//!     typenum::{Prod, U1024, U4},
+//!     A64Bytes, A8Bytes,
+//! };
+//! use mc_oblivious_ram::building_blocks::{
+//!     meta_block_num, meta_is_vacant, meta_leaf_num_mut, meta_set_block_num_if,
+//!     BranchCheckout, MetaSize,
+//! };
+//! use mc_oblivious_traits::HeapORAMStorage;
+//!
+//! // A tree of height 3 (leaves 8..16), with 4 values of 1024 bytes per bucket
+//! let mut storage = HeapORAMStorage::<Prod<U4, U1024>, Prod<U4, MetaSize>>::new(16);
+//! let mut branch = BranchCheckout::<U1024, U4>::default();
+//! branch.checkout(&mut storage, 9);
+//!
+//! // Insert block 7, which belongs on the branch to leaf 9
+//! let data = A64Bytes::<U1024>::default();
+//! let mut meta = A8Bytes::<MetaSize>::default();
+//! *meta_leaf_num_mut(&mut meta) = 9;
+//! meta_set_block_num_if(1.into(), &mut meta, 7);
+//! branch.ct_insert(1.into(), &data, &mut meta);
+//! assert!(bool::from(meta_is_vacant(&meta)));
+//!
+//! // Take it out again
+//! let mut found = A8Bytes::<MetaSize>::default();
+//! let mut found_data = A64Bytes::<U1024>::default();
+//! branch.ct_find_and_remove(1.into(), &7, &mut found_data, &mut found);
+//! assert_eq!(meta_block_num(&found), 7);
+//! branch.checkin(&mut storage);
+//! ```
+
+pub use crate::path_oram::{
+    bucket_has_empty_slot,
+    details::{ct_find_and_remove, ct_insert, ct_take_for_bucket},
+    init_vacant, meta_block_num, meta_is_pinned, meta_is_vacant, meta_leaf_num, meta_leaf_num_mut,
+    meta_set_block_num_if, meta_set_vacant, set_meta_if, BranchCheckout, MetaSize,
+};
@@ -0,0 +1,189 @@
+//! Defines how PathORAM moves items from the stash into a branch on eviction.
+//!
+//! The EvictionStrategy trait is object safe, so that a strategy can be chosen
+//! at runtime, e.g. by name from a configuration, and stored as a
+//! `Box<dyn EvictionStrategy<ValueSize, Z>>`. Custom strategies can be built
+//! from the primitives in `building_blocks`.
+//!
+//! Any strategy must be constant time with respect to the contents of the
+//! stash and branch, since eviction happens on every access.
+
+use crate::building_blocks::{meta_is_pinned, BranchCheckout, MetaSize};
+use aligned_cmov::{
+    typenum::{PartialDiv, Prod, Unsigned, U64, U8},
+    A64Bytes, A8Bytes, ArrayLength,
+};
+use alloc::boxed::Box;
+use core::{fmt, ops::Mul};
+
+/// A procedure to move items from the stash into a checked-out branch.
+///
+/// PathORAM packs the branch before calling `evict`. An implementation must
+/// not move pinned items out of the stash, must only place an item in a bucket
+/// on the branch of its own leaf, and must neither duplicate nor lose items.
+pub trait EvictionStrategy<ValueSize, Z>: Send + Sync
+where
+    ValueSize: ArrayLength<u8> + PartialDiv<U8> + PartialDiv<U64>,
+    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
+    Prod<Z, ValueSize>: ArrayLength<u8> + PartialDiv<U8>,
+    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
+{
+    /// The name of this strategy, as accepted by `eviction_strategy_by_name`
+    /// for the built-in strategies
+    fn name(&self) -> &'static str;
+
+    /// Move items from the stash into the checked-out branch
+    fn evict(
+        &self,
+        branch: &mut BranchCheckout<ValueSize, Z>,
+        stash_data: &mut [A64Bytes<ValueSize>],
+        stash_meta: &mut [A8Bytes<MetaSize>],
+    );
+}
+
+/// For each stash item, insert it into the deepest bucket of the branch where
+/// it can legally go and there is space. This is the default.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ItemFirstEvictionStrategy;
+
+impl<ValueSize, Z> EvictionStrategy<ValueSize, Z> for ItemFirstEvictionStrategy
+where
+    ValueSize: ArrayLength<u8> + PartialDiv<U8> + PartialDiv<U64>,
+    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
+    Prod<Z, ValueSize>: ArrayLength<u8> + PartialDiv<U8>,
+    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
+{
+    fn name(&self) -> &'static str {
+        "item-first"
+    }
+
+    fn evict(
+        &self,
+        branch: &mut BranchCheckout<ValueSize, Z>,
+        stash_data: &mut [A64Bytes<ValueSize>],
+        stash_meta: &mut [A8Bytes<MetaSize>],
+    ) {
+        debug_assert!(stash_data.len() == stash_meta.len());
+        for idx in 0..stash_data.len() {
+            let condition = !meta_is_pinned(&stash_meta[idx]);
+            branch.ct_insert(condition, &stash_data[idx], &mut stash_meta[idx]);
+        }
+    }
+}
+
+/// The names of the built-in eviction strategies
+pub const EVICTION_STRATEGY_NAMES: [&str; 1] = ["item-first"];
+
+/// The error returned when looking up an unknown eviction strategy name
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct UnknownEvictionStrategy;
+
+impl fmt::Display for UnknownEvictionStrategy {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "Unknown eviction strategy")
+    }
+}
+
+/// Get a built-in eviction strategy by its name, see `EVICTION_STRATEGY_NAMES`
+pub fn eviction_strategy_by_name<ValueSize, Z>(
+    name: &str,
+) -> Result<Box<dyn EvictionStrategy<ValueSize, Z>>, UnknownEvictionStrategy>
+where
+    ValueSize: ArrayLength<u8> + PartialDiv<U8> + PartialDiv<U64>,
+    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
+    Prod<Z, ValueSize>: ArrayLength<u8> + PartialDiv<U8>,
+    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
+{
+    match name {
+        "item-first" => Ok(Box::new(ItemFirstEvictionStrategy)),
+        _ => Err(UnknownEvictionStrategy),
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use crate::building_blocks::{
+        meta_block_num, meta_is_vacant, meta_leaf_num_mut, meta_set_block_num_if,
+    };
+    use aligned_cmov::{
+        typenum::{U1, U1024},
+        AsAlignedChunks,
+    };
+    use alloc::{vec, vec::Vec};
+    use mc_oblivious_traits::HeapORAMStorage;
+
+    // Like item-first, but taking stash items from the back
+    struct ReverseItemFirst;
+
+    impl EvictionStrategy<U1024, U1> for ReverseItemFirst {
+        fn name(&self) -> &'static str {
+            "reverse-item-first"
+        }
+
+        fn evict(
+            &self,
+            branch: &mut BranchCheckout<U1024, U1>,
+            stash_data: &mut [A64Bytes<U1024>],
+            stash_meta: &mut [A8Bytes<MetaSize>],
+        ) {
+            for idx in (0..stash_data.len()).rev() {
+                let condition = !meta_is_pinned(&stash_meta[idx]);
+                branch.ct_insert(condition, &stash_data[idx], &mut stash_meta[idx]);
+            }
+        }
+    }
+
+    fn make_meta(leaf: u64, block_num: u64) -> A8Bytes<MetaSize> {
+        let mut result = A8Bytes::<MetaSize>::default();
+        *meta_leaf_num_mut(&mut result) = leaf;
+        meta_set_block_num_if(1.into(), &mut result, block_num);
+        result
+    }
+
+    // Test that evictors of different types can be stored together and each
+    // is invoked through dynamic dispatch. Two stash items compete for the one
+    // vacant slot, at the root, and each evictor picks a different one.
+    #[test]
+    fn dyn_evictors_are_invoked() {
+        let evictors: Vec<Box<dyn EvictionStrategy<U1024, U1>>> = vec![
+            eviction_strategy_by_name("item-first").unwrap(),
+            Box::new(ReverseItemFirst),
+        ];
+        let mut root_blocks = Vec::new();
+        for evictor in evictors.iter() {
+            // A tree of height 2 (leaves 4..8), with one value per bucket
+            let mut storage = HeapORAMStorage::<Prod<U1, U1024>, Prod<U1, MetaSize>>::new(8);
+            let mut branch = BranchCheckout::<U1024, U1>::default();
+            branch.checkout(&mut storage, 4);
+            for (leaf, block_num) in [(4, 10), (4, 11)].iter() {
+                let mut meta = make_meta(*leaf, *block_num);
+                branch.ct_insert(1.into(), &Default::default(), &mut meta);
+                assert!(bool::from(meta_is_vacant(&meta)));
+            }
+
+            let mut stash_data = vec![A64Bytes::<U1024>::default(); 2];
+            let mut stash_meta = vec![make_meta(6, 1), make_meta(7, 2)];
+            evictor.evict(&mut branch, &mut stash_data, &mut stash_meta);
+
+            let (level, _, root_meta) = branch.buckets_root_to_leaf().next().unwrap();
+            assert_eq!(level, 0);
+            let root_meta: &[A8Bytes<MetaSize>] = root_meta.as_aligned_chunks();
+            root_blocks.push((evictor.name(), meta_block_num(&root_meta[0])));
+            assert_eq!(
+                stash_meta
+                    .iter()
+                    .filter(|meta| !bool::from(meta_is_vacant(meta)))
+                    .count(),
+                1
+            );
+            branch.check_invariants(&stash_meta);
+            branch.checkin(&mut storage);
+        }
+        assert_eq!(
+            root_blocks,
+            vec![("item-first", 1), ("reverse-item-first", 2)]
+        );
+        assert!(eviction_strategy_by_name::<U1024, U1>("bucket-first").is_err());
+    }
+}
@@ -0,0 +1,79 @@
+//! Defines how PathORAM chooses a new leaf for a block on each access.
+//!
+//! In the Path ORAM paper, every accessed block is reassigned a uniformly random
+//! leaf, and this is what the security proof relies on. The LeafAssigner trait
+//! makes this choice pluggable, mainly so that alternative distributions can be
+//! studied, for instance to see their effect on the stash size.
+//!
+//! Any assigner other than the uniform one should be considered experimental.
+//! The leaf assigned to a block is revealed by the next access to that block,
+//! so a non-uniform assigner must only depend on information that is not secret.
+
+use balanced_tree_index::TreeIndex;
+use rand_core::{CryptoRng, RngCore};
+
+/// A strategy for choosing the new (secret) leaf of a block when it is accessed.
+pub trait LeafAssigner<R: RngCore + CryptoRng> {
+    /// Choose a leaf for a block, in a tree where the leaves are at the given height.
+    ///
+    /// The result must be a leaf of the tree, that is, a TreeIndex value at
+    /// the given height.
+    fn assign_leaf(&mut self, height: u32, rng: &mut R) -> u64;
+}
+
+/// The standard Path ORAM leaf assignment: a uniformly random leaf.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct UniformLeafAssigner;
+
+impl<R: RngCore + CryptoRng> LeafAssigner<R> for UniformLeafAssigner {
+    fn assign_leaf(&mut self, height: u32, rng: &mut R) -> u64 {
+        1u64.random_child_at_height(height, rng)
+    }
+}
+
+/// Assigns a uniformly random leaf among the leaves under a fixed subtree.
+///
+/// Only the low bits of the leaf, below the subtree root, are random, so this
+/// can be used to map an external addressing scheme which only uses some of
+/// the leaf bits onto a part of the tree. Blocks are then only ever evicted
+/// into the branches through that subtree, and the tree is not resized.
+///
+/// Because every assigned leaf is under the same subtree, this reveals nothing
+/// new by itself, but it concentrates load: the stash must be sized for
+/// a tree of the subtree's size.
+#[derive(Copy, Clone, Debug)]
+pub struct SubtreeLeafAssigner {
+    subtree: u64,
+}
+
+impl SubtreeLeafAssigner {
+    /// Assign leaves under the given subtree root, which is a TreeIndex value
+    pub fn new(subtree: u64) -> Self {
+        assert!(subtree != 0, "subtree root cannot be the NONE value");
+        Self { subtree }
+    }
+
+    /// Assign leaves whose low `bits` bits are random, and whose remaining bits
+    /// (below the leading 1) are given by `prefix`, in a tree of the given height.
+    pub fn with_significant_bits(height: u32, bits: u32, prefix: u64) -> Self {
+        assert!(bits <= height, "more significant bits than the tree height");
+        let prefix_bits = height - bits;
+        assert!(prefix >> prefix_bits == 0, "prefix has too many bits");
+        Self::new((1u64 << prefix_bits) | prefix)
+    }
+
+    /// The root of the subtree that leaves are assigned under
+    pub fn subtree(&self) -> u64 {
+        self.subtree
+    }
+}
+
+impl<R: RngCore + CryptoRng> LeafAssigner<R> for SubtreeLeafAssigner {
+    fn assign_leaf(&mut self, height: u32, rng: &mut R) -> u64 {
+        assert!(
+            self.subtree.height() <= height,
+            "subtree root is below the leaves"
+        );
+        self.subtree.random_child_at_height(height, rng)
+    }
+}
@@ -23,6 +23,9 @@
 
 extern crate alloc;
 
+#[cfg(feature = "tracing")]
+extern crate tracing_crate as tracing;
+
 use aligned_cmov::typenum::{U1024, U2, U2048, U32, U4, U4096, U64};
 use core::marker::PhantomData;
 use mc_oblivious_traits::{ORAMCreator, ORAMStorageCreator};
@@ -31,8 +34,33 @@ use rand_core::{CryptoRng, RngCore};
 mod position_map;
 pub use position_map::{ORAMU32PositionMap, TrivialPositionMap, U32PositionMapCreator};
 
+mod bucket_size;
+pub use bucket_size::{recommend_z, MAX_LOAD_FACTOR};
+
+mod bucket_layout;
+pub use bucket_layout::{read_bucket, write_bucket, BucketLayout, SplitLayout};
+
+mod parity_storage;
+pub use parity_storage::{ParityORAMStorage, ParityORAMStorageCreator};
+
+mod leaf_assigner;
+pub use leaf_assigner::{LeafAssigner, SubtreeLeafAssigner, UniformLeafAssigner};
+
+mod eviction_strategy;
+pub use eviction_strategy::{
+    eviction_strategy_by_name, EvictionStrategy, ItemFirstEvictionStrategy,
+    UnknownEvictionStrategy, EVICTION_STRATEGY_NAMES,
+};
+
+pub mod building_blocks;
+
 mod path_oram;
-pub use path_oram::PathORAM;
+pub use path_oram::{
+    branch_len, leaves_under_bucket, reverse_lex_schedule, stash_adjusted_len, BranchSelector,
+    ORAMError, OverflowPolicy, PathORAM, StashPlacement, TraceAccess, UnknownBranchSelector,
+};
+#[cfg(feature = "eviction_log")]
+pub use path_oram::{EvictionRecord, EvictionSink};
 
 /// Creator for PathORAM based on 4096-sized blocks of storage and bucket size (Z) of 2,
 /// and a basic recursive position map implementation
@@ -96,11 +124,19 @@ mod testing {
     use super::*;
 
     use aligned_cmov::{A64Bytes, A8Bytes, ArrayLength};
-    use mc_oblivious_traits::{rng_maker, testing, HeapORAMStorageCreator, ORAM};
-    use test_helper::{run_with_several_seeds, RngType};
+    use alloc::{boxed::Box, string::ToString, sync::Arc, vec, vec::Vec};
+    use balanced_tree_index::TreeIndex;
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use mc_oblivious_traits::{
+        log2_ceil, rng_maker, testing, testing::ChecksummingORAM, HeapORAMStorage,
+        HeapORAMStorageCreator, ORAM,
+    };
+    use test_helper::{run_with_one_seed, run_with_several_seeds, RngType};
 
     const STASH_SIZE: usize = 16;
 
+    pub(crate) type TestORAM = PathORAM<U1024, U4, HeapORAMStorage<U4096, U64>, RngType>;
+
     // Helper to make tests more succinct
     #[allow(unused)]
     fn a8_bytes<N: ArrayLength<u8>>(src: u8) -> A8Bytes<N> {
@@ -111,7 +147,7 @@ mod testing {
         result
     }
 
-    fn a64_bytes<N: ArrayLength<u8>>(src: u8) -> A64Bytes<N> {
+    pub(crate) fn a64_bytes<N: ArrayLength<u8>>(src: u8) -> A64Bytes<N> {
         let mut result = A64Bytes::<N>::default();
         for byte in result.iter_mut() {
             *byte = src;
@@ -119,6 +155,35 @@ mod testing {
         result
     }
 
+    // Make the ORAM which most tests use, of 1024 values with a stash of 16
+    pub(crate) fn new_test_oram<M: 'static + FnMut() -> RngType>(maker: &mut M) -> TestORAM {
+        PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(1024, 16, maker)
+    }
+
+    // Fill an ORAM, then do random accesses to it, checking the results
+    pub(crate) fn fill_and_exercise<O: ORAM<U1024>>(
+        oram: &mut O,
+        num_rounds: usize,
+        rng: &mut RngType,
+    ) {
+        let len = oram.len();
+        let mut expected = vec![0u8; len as usize];
+        for idx in 0..len {
+            let val = rng.next_u32() as u8;
+            oram.write(idx, &a64_bytes(val));
+            expected[idx as usize] = val;
+        }
+        for _ in 0..num_rounds {
+            let idx = rng.next_u64() % len;
+            let val = rng.next_u32() as u8;
+            assert_eq!(
+                oram.write(idx, &a64_bytes(val)),
+                a64_bytes(expected[idx as usize])
+            );
+            expected[idx as usize] = val;
+        }
+    }
+
     // Sanity check the standard z2 path oram
     #[test]
     fn sanity_check_path_oram_z2_1024() {
@@ -366,4 +431,650 @@ mod testing {
             testing::exercise_oram(60_000, &mut oram, &mut rng);
         });
     }
+
+    // Test that the smallest orams, whose trees are just a root bucket, work
+    #[test]
+    fn tiny_path_oram() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            for size in [1u64, 2].iter() {
+                let mut oram = PathORAM4096Z2Creator::<RngType, HeapORAMStorageCreator>::create(
+                    *size, STASH_SIZE, &mut maker,
+                );
+                assert_eq!(oram.len(), *size);
+                for key in 0..*size {
+                    assert_eq!(a64_bytes(0), oram.write(key, &a64_bytes(key as u8 + 1)));
+                }
+                for key in 0..*size {
+                    assert_eq!(a64_bytes(key as u8 + 1), oram.read(key));
+                }
+
+                let mut oram = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                    *size, STASH_SIZE, &mut maker,
+                );
+                assert_eq!(oram.len(), *size);
+                for key in 0..*size {
+                    assert_eq!(a64_bytes(0), oram.write(key, &a64_bytes(key as u8 + 1)));
+                }
+                for key in 0..*size {
+                    assert_eq!(a64_bytes(key as u8 + 1), oram.read(key));
+                }
+            }
+        });
+    }
+
+    // Run the exercise oram tests for 1,000 rounds in the smallest z4 orams
+    #[test]
+    fn exercise_tiny_path_oram() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            for size in [1u64, 2].iter() {
+                let mut oram = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                    *size, STASH_SIZE, &mut maker,
+                );
+                testing::exercise_oram(1_000, &mut oram, &mut rng);
+            }
+        });
+    }
+
+    // Test that an oram of size zero is rejected up front
+    #[test]
+    #[should_panic(expected = "size cannot be zero")]
+    fn zero_size_path_oram() {
+        run_with_several_seeds(|rng| {
+            PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                0,
+                STASH_SIZE,
+                &mut rng_maker(rng),
+            );
+        });
+    }
+
+    // A leaf assigner which counts how many times it is invoked, and otherwise
+    // behaves like the uniform one
+    struct CountingLeafAssigner {
+        count: Arc<AtomicU64>,
+    }
+
+    impl LeafAssigner<RngType> for CountingLeafAssigner {
+        fn assign_leaf(&mut self, height: u32, rng: &mut RngType) -> u64 {
+            self.count.fetch_add(1, Ordering::SeqCst);
+            UniformLeafAssigner.assign_leaf(height, rng)
+        }
+    }
+
+    // Test that a custom leaf assigner is invoked once per access
+    #[test]
+    fn custom_leaf_assigner_is_invoked() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut oram = new_test_oram(&mut maker);
+            let count = Arc::new(AtomicU64::new(0));
+            oram.set_leaf_assigner(Box::new(CountingLeafAssigner {
+                count: count.clone(),
+            }));
+
+            fill_and_exercise(&mut oram, 500, &mut rng);
+            assert_eq!(count.load(Ordering::SeqCst), 1024 + 500);
+        });
+    }
+
+    // Test that the checksum over access results is the same for two numbers
+    // of eviction rounds on the same workload, and changes with the workload
+    #[test]
+    fn result_checksum_matches_across_eviction_rounds() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let workload_rng = maker();
+            let mut checksums = Vec::new();
+            for (rounds, seed_offset) in [(1u32, 0u8), (2, 0), (1, 1)].iter() {
+                let mut oram = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                    256, 16, &mut maker,
+                );
+                oram.set_eviction_rounds(*rounds);
+                let mut oram = ChecksummingORAM::new(oram);
+                let mut rng = workload_rng.clone();
+                for _ in 0..500 {
+                    let idx = rng.next_u64() % 256;
+                    oram.write(idx, &a64_bytes(rng.next_u32() as u8 ^ seed_offset));
+                }
+                checksums.push(oram.checksum());
+            }
+            assert_eq!(checksums[0], checksums[1]);
+            assert_ne!(checksums[0], checksums[2]);
+        });
+    }
+
+    // Test that delete_where deletes exactly the matching blocks
+    #[test]
+    fn delete_where_by_value() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = new_test_oram(&mut maker);
+            for key in 0..1024 {
+                oram.write(key, &a64_bytes((key % 7) as u8 + 1));
+            }
+
+            // Delete all blocks whose value is even, keeping the running checksum
+            oram.enable_checksum();
+            oram.delete_where(|_, val| val[0] & 1 == 0);
+            assert!(oram.verify_checksum());
+
+            for key in 0..1024 {
+                let val = (key % 7) as u8 + 1;
+                if val & 1 == 0 {
+                    assert_eq!(oram.read(key), a64_bytes(0));
+                } else {
+                    assert_eq!(oram.read(key), a64_bytes(val));
+                }
+            }
+
+            // Deleted blocks can be written again
+            assert_eq!(oram.write(1, &a64_bytes(9)), a64_bytes(0));
+            assert_eq!(oram.read(1), a64_bytes(9));
+        });
+    }
+
+    // Test that the read and write helpers agree with each other, and with access
+    #[test]
+    fn read_write_compose_with_access() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = new_test_oram(&mut maker);
+            assert_eq!(oram.read(9), a64_bytes(0));
+            assert_eq!(oram.write(9, &a64_bytes(4)), a64_bytes(0));
+            assert_eq!(oram.read(9), a64_bytes(4));
+
+            // A value written with access is seen by read, and vice versa
+            oram.access(9, |val| {
+                assert_eq!(*val, a64_bytes(4));
+                *val = a64_bytes(5);
+            });
+            assert_eq!(oram.read(9), a64_bytes(5));
+            assert_eq!(oram.write(9, &a64_bytes(6)), a64_bytes(5));
+            assert_eq!(oram.access(9, |val| val[0]), 6);
+
+            // Other keys are unaffected
+            assert_eq!(oram.read(8), a64_bytes(0));
+            assert_eq!(oram.read(10), a64_bytes(0));
+        });
+    }
+
+    // Test that a read-only access sees the right value, and leaves it unchanged
+    #[test]
+    fn access_ref_does_not_modify() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = new_test_oram(&mut maker);
+            oram.write(3, &a64_bytes(7));
+            for _ in 0..20 {
+                assert_eq!(oram.access_ref(3, |val| val[0]), 7);
+            }
+            assert!(oram.access_ref(3, |val| *val == a64_bytes(7)));
+            assert_eq!(oram.read(3), a64_bytes(7));
+            assert_eq!(oram.read(4), a64_bytes(0));
+        });
+    }
+
+    // Test that the digest depends only on the logical contents: a rebuilt copy
+    // matches, as after a save and restore, and a modified one doesn't
+    #[test]
+    fn digest_tracks_contents() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut original = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                256, 16, &mut maker,
+            );
+            let mut restored = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                256, 16, &mut maker,
+            );
+            let empty_digest = original.digest();
+            assert_eq!(empty_digest, [0u8; 32]);
+
+            for key in 0..100 {
+                original.write(key, &a64_bytes(key as u8));
+            }
+            // Restore in a different order, into a differently seeded ORAM
+            for key in (0..100).rev() {
+                restored.write(key, &a64_bytes(key as u8));
+            }
+            let digest = original.digest();
+            assert_ne!(digest, empty_digest);
+            assert_eq!(restored.digest(), digest);
+
+            // Reads move blocks around, but don't change the digest
+            for key in 0..100 {
+                original.read(key);
+            }
+            assert_eq!(original.digest(), digest);
+
+            restored.write(42, &a64_bytes(43));
+            assert_ne!(restored.digest(), digest);
+            restored.write(42, &a64_bytes(42));
+            assert_eq!(restored.digest(), digest);
+        });
+    }
+
+    // Test that ORAMs with the same contents compare equal, even when they were
+    // built in different ways
+    #[test]
+    fn states_equal_ignores_layout() {
+        use mc_oblivious_traits::{states_equal, LinearScanningORAM};
+
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut forward = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                256, 16, &mut maker,
+            );
+            let mut backward = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                256, 16, &mut maker,
+            );
+            backward.set_eviction_rounds(2);
+            let mut linear = LinearScanningORAM::<U1024>::new(256);
+
+            // Write the same final values, in a different order and with
+            // different intermediate values
+            for key in 0..256 {
+                forward.write(key, &a64_bytes(key as u8));
+                linear.write(255 - key, &a64_bytes((255 - key) as u8));
+            }
+            for key in (0..256).rev() {
+                backward.write(key, &a64_bytes(0xff));
+                backward.write(key, &a64_bytes(key as u8));
+            }
+            assert!(states_equal(&mut forward, &mut backward));
+            assert!(states_equal(&mut forward, &mut linear));
+
+            backward.write(17, &a64_bytes(0));
+            assert!(!states_equal(&mut forward, &mut backward));
+            assert!(!states_equal(
+                &mut forward,
+                &mut LinearScanningORAM::<U1024>::new(128)
+            ));
+        });
+    }
+
+    // Test that upsert inserts new keys, and updates existing keys in place
+    #[test]
+    fn upsert_inserts_or_updates() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = new_test_oram(&mut maker);
+            let increment = |val: &mut A64Bytes<U1024>| val[0] = val[0].wrapping_add(1);
+            oram.write(3, &a64_bytes(5));
+
+            // A new key gets the value
+            let existed = oram.upsert(7, &a64_bytes(10), increment).unwrap();
+            assert!(!bool::from(existed));
+            assert_eq!(oram.read(7), a64_bytes(10));
+
+            // An existing key gets the closure applied
+            let existed = oram.upsert(7, &a64_bytes(10), increment).unwrap();
+            assert!(bool::from(existed));
+            let mut expected = a64_bytes(10);
+            expected[0] = 11;
+            assert_eq!(oram.read(7), expected);
+
+            let existed = oram.upsert(3, &a64_bytes(10), increment).unwrap();
+            assert!(bool::from(existed));
+            let mut expected = a64_bytes(5);
+            expected[0] = 6;
+            assert_eq!(oram.read(3), expected);
+
+            // Reading a key doesn't make it exist
+            assert_eq!(oram.read(9), a64_bytes(0));
+            let existed = oram.upsert(9, &a64_bytes(10), increment).unwrap();
+            assert!(!bool::from(existed));
+            assert_eq!(oram.read(9), a64_bytes(10));
+
+            // Unrelated keys are unchanged
+            for key in 0..1024 {
+                if key != 3 && key != 7 && key != 9 {
+                    assert_eq!(oram.read(key), a64_bytes(0));
+                }
+            }
+            assert!(matches!(
+                oram.upsert(1024, &a64_bytes(1), increment),
+                Err(ORAMError::KeyOutOfBounds)
+            ));
+        });
+    }
+
+    // Test that metrics_text produces valid Prometheus metrics which reflect
+    // a known workload
+    #[test]
+    #[cfg(feature = "metrics")]
+    fn metrics_text_reflects_workload() {
+        use alloc::{collections::BTreeMap, string::String};
+
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut oram = new_test_oram(&mut maker);
+            fill_and_exercise(&mut oram, 500, &mut rng);
+            oram.set_sibling_eviction_level(Some(1));
+            fill_and_exercise(&mut oram, 100, &mut rng);
+
+            // Parse the exposition format: every sample must be preceded by
+            // HELP and TYPE lines for the same metric name, and have a numeric value.
+            let text = oram.metrics_text();
+            let mut samples = BTreeMap::<String, f64>::new();
+            let mut typed = None;
+            for line in text.lines() {
+                if let Some(rest) = line.strip_prefix("# HELP ") {
+                    assert!(rest.split(' ').count() > 1, "bad HELP line: {}", line);
+                } else if let Some(rest) = line.strip_prefix("# TYPE ") {
+                    let mut parts = rest.split(' ');
+                    typed = parts.next().map(String::from);
+                    assert!(matches!(parts.next(), Some("counter") | Some("gauge")));
+                } else {
+                    let mut parts = line.split(' ');
+                    let name = parts.next().unwrap();
+                    let value: f64 = parts.next().unwrap().parse().expect("bad value");
+                    assert!(parts.next().is_none());
+                    assert!(name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':'));
+                    assert_eq!(typed.as_deref(), Some(name), "sample without TYPE");
+                    samples.insert(String::from(name), value);
+                }
+            }
+
+            let num_accesses = (1024 + 500 + 1024 + 100) as f64;
+            assert_eq!(samples["mc_oblivious_ram_accesses_total"], num_accesses);
+            assert_eq!(
+                samples["mc_oblivious_ram_evictions_total"],
+                num_accesses + (1024 + 100) as f64
+            );
+            assert!(samples["mc_oblivious_ram_max_stash_occupancy"] <= 16.0);
+            assert_eq!(samples["mc_oblivious_ram_stash_size"], 16.0);
+            assert_eq!(
+                samples["mc_oblivious_ram_load_factor"],
+                1024.0 / (511.0 * 4.0)
+            );
+        });
+    }
+
+    // Test that accesses emit the expected tracing spans, and that the spans
+    // only carry structural fields
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_spans_are_structural() {
+        extern crate std;
+        use alloc::string::String;
+        use std::sync::Mutex;
+        use tracing::{
+            span::{Attributes, Id, Record},
+            subscriber::with_default,
+            Event, Metadata, Subscriber,
+        };
+
+        type SpanList = Arc<Mutex<Vec<(String, Vec<String>)>>>;
+
+        // Records the name and field names of each new span
+        struct SpanRecorder {
+            spans: SpanList,
+        }
+
+        impl Subscriber for SpanRecorder {
+            fn enabled(&self, _metadata: &Metadata) -> bool {
+                true
+            }
+            fn new_span(&self, attrs: &Attributes) -> Id {
+                let mut spans = self.spans.lock().unwrap();
+                let fields = attrs
+                    .metadata()
+                    .fields()
+                    .iter()
+                    .map(|field| field.name().to_string())
+                    .collect();
+                spans.push((attrs.metadata().name().to_string(), fields));
+                Id::from_u64(spans.len() as u64)
+            }
+            fn record(&self, _span: &Id, _values: &Record) {}
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+            fn event(&self, _event: &Event) {}
+            fn enter(&self, _span: &Id) {}
+            fn exit(&self, _span: &Id) {}
+        }
+
+        let spans = SpanList::default();
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                256, 16, &mut maker,
+            );
+            spans.lock().unwrap().clear();
+            with_default(
+                SpanRecorder {
+                    spans: spans.clone(),
+                },
+                || {
+                    oram.write(17, &a64_bytes(3));
+                },
+            );
+            let spans = spans.lock().unwrap();
+            for name in ["oram_access", "oram_checkout", "oram_evict", "oram_checkin"].iter() {
+                assert!(
+                    spans.iter().any(|(span_name, _)| span_name == name),
+                    "missing span {}",
+                    name
+                );
+            }
+            let allowed = ["height", "strategy", "buckets"];
+            for (name, fields) in spans.iter() {
+                for field in fields {
+                    assert!(
+                        allowed.contains(&field.as_str()),
+                        "span {} has unexpected field {}",
+                        name,
+                        field
+                    );
+                }
+            }
+        });
+    }
+
+    // Test the structural getters against the sizes they are derived from
+    #[test]
+    fn structural_getters() {
+        run_with_one_seed(|rng| {
+            let mut maker = rng_maker(rng);
+            for size in [1u64, 2, 4, 8, 64, 1024].iter() {
+                let oram = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                    *size, 16, &mut maker,
+                );
+                let height = log2_ceil(*size).saturating_sub(log2_ceil(4));
+                assert_eq!(oram.len(), *size);
+                assert_eq!(oram.tree_height(), height);
+                assert_eq!(oram.bucket_size(), 4);
+                assert_eq!(oram.leaf_count(), 1 << height);
+            }
+        });
+    }
+
+    // Test that the leaves under each bucket are the ones whose branches pass
+    // through it, and that the buckets at each level partition the leaves
+    #[test]
+    fn leaves_under_bucket_partition() {
+        assert_eq!(leaves_under_bucket(1, 0), 1..2);
+        assert_eq!(leaves_under_bucket(1, 3), 8..16);
+        assert_eq!(leaves_under_bucket(3, 3), 12..16);
+        assert_eq!(leaves_under_bucket(9, 3), 9..10);
+
+        for height in 0..8u32 {
+            for level in 0..=height {
+                let mut next = 1u64 << height;
+                for bucket in (1u64 << level)..(2u64 << level) {
+                    let leaves = leaves_under_bucket(bucket, height);
+                    assert_eq!(leaves.end - leaves.start, 1u64 << (height - level));
+                    assert_eq!(leaves.start, next);
+                    next = leaves.end;
+                    for leaf in leaves {
+                        assert!(leaf.parents().any(|p| p == bucket));
+                    }
+                }
+                assert_eq!(next, 2u64 << height);
+            }
+        }
+    }
+
+    // Select eviction strategies at runtime by name, and exercise each of them
+    #[test]
+    fn eviction_strategy_by_name() {
+        for name in EVICTION_STRATEGY_NAMES.iter() {
+            let strategy = crate::eviction_strategy_by_name::<U1024, U4>(name).unwrap();
+            assert_eq!(strategy.name(), *name);
+        }
+        assert_eq!(
+            crate::eviction_strategy_by_name::<U1024, U4>("bucket-first").err(),
+            Some(UnknownEvictionStrategy)
+        );
+
+        run_with_one_seed(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            for name in EVICTION_STRATEGY_NAMES.iter() {
+                let mut oram = new_test_oram(&mut maker);
+                oram.set_eviction_strategy(crate::eviction_strategy_by_name(name).unwrap());
+                fill_and_exercise(&mut oram, 500, &mut rng);
+            }
+        });
+    }
+
+    // Parse branch selectors from their names, check that they display as the
+    // same names, and exercise each of them
+    #[test]
+    fn branch_selector_by_name() {
+        let selectors: Vec<BranchSelector> = ["reverse-lex", "stalest-subtree", "depth-first"]
+            .iter()
+            .map(|name| name.parse().unwrap())
+            .collect();
+        assert_eq!(selectors, BranchSelector::ALL);
+        assert_eq!(
+            "circuit".parse::<BranchSelector>(),
+            Err(UnknownBranchSelector)
+        );
+
+        for selector in selectors.iter() {
+            assert_eq!(selector.to_string().parse(), Ok(*selector));
+        }
+
+        run_with_one_seed(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            for selector in selectors.iter() {
+                let mut oram = new_test_oram(&mut maker);
+                oram.set_eviction_rounds(2);
+                oram.set_branch_selector(*selector);
+                fill_and_exercise(&mut oram, 500, &mut rng);
+            }
+        });
+    }
+
+    // Test the stash needed for a trace which overfills one branch, which is
+    // the number of blocks which don't fit in the branch, plus the accessed one
+    #[test]
+    fn min_stash_for_overfilled_branch() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            // 16 items with Z = 4 gives height 2, so a branch holds 12 blocks
+            assert_eq!(TestORAM::height_for_size(16), 2);
+            let access = |key| TraceAccess {
+                key,
+                leaf: 4,
+                new_leaf: 4,
+            };
+            // After 12 blocks, each block stays in the stash, so the 16th block
+            // joins 3 others there
+            let mut trace: Vec<TraceAccess> = (0..16).map(access).collect();
+            assert_eq!(
+                TestORAM::min_stash_for_trace::<HeapORAMStorageCreator, _>(16, &trace, &mut maker),
+                4
+            );
+            // Blocks already in the stash don't need another slot
+            trace.push(access(15));
+            trace.push(access(13));
+            assert_eq!(
+                TestORAM::min_stash_for_trace::<HeapORAMStorageCreator, _>(16, &trace, &mut maker),
+                4
+            );
+            // A block from the full branch passes through the stash too
+            trace.push(access(0));
+            assert_eq!(
+                TestORAM::min_stash_for_trace::<HeapORAMStorageCreator, _>(16, &trace, &mut maker),
+                5
+            );
+            // A trace which spreads blocks out needs one slot, for the access
+            let trace: Vec<TraceAccess> = (0..4)
+                .map(|key| TraceAccess {
+                    key,
+                    leaf: 4 + key,
+                    new_leaf: 4 + key,
+                })
+                .collect();
+            assert_eq!(
+                TestORAM::min_stash_for_trace::<HeapORAMStorageCreator, _>(16, &trace, &mut maker),
+                1
+            );
+        });
+    }
+
+    // Test that a trace which checks a key out at the wrong leaf is rejected
+    #[test]
+    #[should_panic(expected = "trace checks out leaf")]
+    fn min_stash_for_inconsistent_trace() {
+        use rand_core::SeedableRng;
+        let mut maker = rng_maker(RngType::from_seed([3u8; 32]));
+        let trace = [
+            TraceAccess {
+                key: 0,
+                leaf: 4,
+                new_leaf: 5,
+            },
+            TraceAccess {
+                key: 0,
+                leaf: 4,
+                new_leaf: 4,
+            },
+        ];
+        TestORAM::min_stash_for_trace::<HeapORAMStorageCreator, _>(16, &trace, &mut maker);
+    }
+
+    // Test that prewarm completes, and the data is still correct afterwards
+    #[test]
+    fn prewarm_preserves_data() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut oram = new_test_oram(&mut maker);
+            oram.prewarm();
+            for key in 0..1024 {
+                oram.write(key, &a64_bytes(key as u8));
+            }
+            oram.prewarm();
+            for key in 0..1024 {
+                assert_eq!(oram.read(key), a64_bytes(key as u8));
+            }
+            fill_and_exercise(&mut oram, 500, &mut rng);
+        });
+    }
+
+    // Test that key scrambling can't be enabled after an access, since blocks
+    // written before it would become unreachable
+    #[test]
+    #[should_panic(expected = "key scrambling must be enabled before the first access")]
+    fn key_scrambling_rejected_after_access() {
+        run_with_one_seed(|rng| {
+            let mut oram = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                1024,
+                STASH_SIZE,
+                &mut rng_maker(rng),
+            );
+            oram.write(0, &a64_bytes(1));
+            oram.enable_key_scrambling();
+        });
+    }
 }
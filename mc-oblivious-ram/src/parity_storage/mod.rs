@@ -0,0 +1,218 @@
+//! A wrapper around ORAM storage which can reconstruct a corrupted bucket,
+//! from redundancy kept in memory.
+//!
+//! Each pair of sibling buckets has a parity bucket, which is the XOR of the
+//! two (the root is paired with an imaginary bucket which is all zeroes), and
+//! each bucket has a keyed hash of its index and contents. When a branch is
+//! checked out, the hash of each of its buckets is checked, and a bucket which
+//! doesn't match is reconstructed as the XOR of its parity and its sibling,
+//! which is read by checking out a branch through it. When a branch is checked
+//! in, the parities and hashes of its buckets are updated, which only depends
+//! on the (public) leaf.
+//!
+//! This corrects any corruption of one bucket of each pair. If both buckets of
+//! a pair are corrupted, the reconstruction doesn't match the hash, and the
+//! checkout panics. A repair is revealed by the extra checkouts, but this only
+//! happens when the storage is faulty, which is not secret.
+//!
+//! The parities take half as much memory as the storage itself, so this is
+//! for storage which is less reliable than the memory holding the ORAM, e.g.
+//! untrusted memory or disk.
+
+use crate::path_oram::StorageChecksum;
+use aligned_cmov::{A64Bytes, A8Bytes, ArrayLength};
+use alloc::{vec, vec::Vec};
+use balanced_tree_index::TreeIndex;
+use core::marker::PhantomData;
+use mc_oblivious_traits::{ORAMStorage, ORAMStorageCreator};
+use rand_core::{CryptoRng, RngCore};
+
+/// ORAM storage with parity buckets, which corrects the corruption of one
+/// bucket of each pair of siblings in the underlying storage
+pub struct ParityORAMStorage<BlockSize, MetaSize, S>
+where
+    BlockSize: ArrayLength<u8>,
+    MetaSize: ArrayLength<u8>,
+    S: ORAMStorage<BlockSize, MetaSize>,
+{
+    /// The underlying storage
+    inner: S,
+    /// The parity of the buckets 2i and 2i + 1, for each i
+    parity_data: Vec<A64Bytes<BlockSize>>,
+    parity_meta: Vec<A8Bytes<MetaSize>>,
+    /// The keyed hash of each bucket, by index
+    hashes: Vec<u64>,
+    /// The keys of the bucket hashes
+    hasher: StorageChecksum,
+    /// The contents of the checked-out branch, as returned by checkout
+    old_data: Vec<A64Bytes<BlockSize>>,
+    old_meta: Vec<A8Bytes<MetaSize>>,
+}
+
+impl<BlockSize, MetaSize, S> ParityORAMStorage<BlockSize, MetaSize, S>
+where
+    BlockSize: ArrayLength<u8>,
+    MetaSize: ArrayLength<u8>,
+    S: ORAMStorage<BlockSize, MetaSize>,
+{
+    /// Add parity to storage which is all zeroes, as it is when created
+    pub fn new<R: RngCore + CryptoRng>(inner: S, rng: &mut R) -> Self {
+        let len = inner.len();
+        let hasher = StorageChecksum::new(rng);
+        let zero_data = A64Bytes::<BlockSize>::default();
+        let zero_meta = A8Bytes::<MetaSize>::default();
+        let hashes = (0..len)
+            .map(|index| hasher.bucket_hash(index, &zero_data, &zero_meta))
+            .collect();
+        Self {
+            inner,
+            parity_data: vec![Default::default(); (len / 2) as usize],
+            parity_meta: vec![Default::default(); (len / 2) as usize],
+            hashes,
+            hasher,
+            old_data: Vec::new(),
+            old_meta: Vec::new(),
+        }
+    }
+
+    /// Get the underlying storage, e.g. for tooling. Changes made through this
+    /// are treated as corruption.
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Check if a bucket matches its hash
+    fn is_intact(&self, index: u64, data: &A64Bytes<BlockSize>, meta: &A8Bytes<MetaSize>) -> bool {
+        self.hasher.bucket_hash(index, data, meta) == self.hashes[index as usize]
+    }
+
+    /// Read the bucket at a given position of a branch, by checking out the
+    /// branch from the underlying storage, and checking it back in unchanged
+    fn read_bucket(&mut self, leaf: u64, idx: usize) -> (A64Bytes<BlockSize>, A8Bytes<MetaSize>) {
+        let branch_len = leaf.height() as usize + 1;
+        let mut data = vec![A64Bytes::<BlockSize>::default(); branch_len];
+        let mut meta = vec![A8Bytes::<MetaSize>::default(); branch_len];
+        self.inner.checkout(leaf, &mut data, &mut meta);
+        let result = (data[idx].clone(), meta[idx].clone());
+        self.inner.checkin(leaf, &mut data, &mut meta);
+        result
+    }
+
+    /// Reconstruct the buckets of a checked-out branch which don't match their
+    /// hashes, from their parities and siblings
+    fn repair(
+        &mut self,
+        leaf: u64,
+        dest: &mut [A64Bytes<BlockSize>],
+        dest_meta: &mut [A8Bytes<MetaSize>],
+    ) {
+        // Check the branch back in unchanged, so that siblings can be read
+        let mut data = dest.to_vec();
+        let mut meta = dest_meta.to_vec();
+        self.inner.checkin(leaf, &mut data, &mut meta);
+        for idx in 0..dest.len() {
+            let index = leaf >> idx;
+            if self.is_intact(index, &dest[idx], &dest_meta[idx]) {
+                continue;
+            }
+            let mut data = self.parity_data[(index / 2) as usize].clone();
+            let mut meta = self.parity_meta[(index / 2) as usize].clone();
+            if index != 1 {
+                // Any branch through the sibling has it at the same position
+                let (sibling_data, sibling_meta) = self.read_bucket(leaf ^ (1 << idx), idx);
+                xor_into(&mut data, &sibling_data);
+                xor_into(&mut meta, &sibling_meta);
+            }
+            assert!(
+                self.is_intact(index, &data, &meta),
+                "unrecoverable corruption of storage bucket {}",
+                index
+            );
+            dest[idx] = data;
+            dest_meta[idx] = meta;
+        }
+        let mut data = dest.to_vec();
+        let mut meta = dest_meta.to_vec();
+        self.inner.checkout(leaf, &mut data, &mut meta);
+    }
+}
+
+impl<BlockSize, MetaSize, S> ORAMStorage<BlockSize, MetaSize>
+    for ParityORAMStorage<BlockSize, MetaSize, S>
+where
+    BlockSize: ArrayLength<u8>,
+    MetaSize: ArrayLength<u8>,
+    S: ORAMStorage<BlockSize, MetaSize>,
+{
+    fn len(&self) -> u64 {
+        self.inner.len()
+    }
+    fn checkout(
+        &mut self,
+        index: u64,
+        dest: &mut [A64Bytes<BlockSize>],
+        dest_meta: &mut [A8Bytes<MetaSize>],
+    ) {
+        self.inner.checkout(index, dest, dest_meta);
+        let intact =
+            (0..dest.len()).all(|idx| self.is_intact(index >> idx, &dest[idx], &dest_meta[idx]));
+        if !intact {
+            self.repair(index, dest, dest_meta);
+        }
+        // Reuse the buffers, which have the branch length after the first checkout
+        self.old_data.resize(dest.len(), Default::default());
+        self.old_meta.resize(dest.len(), Default::default());
+        self.old_data.clone_from_slice(dest);
+        self.old_meta.clone_from_slice(dest_meta);
+    }
+    fn checkin(
+        &mut self,
+        index: u64,
+        src: &mut [A64Bytes<BlockSize>],
+        src_meta: &mut [A8Bytes<MetaSize>],
+    ) {
+        debug_assert!(self.old_data.len() == src.len(), "checkin without checkout");
+        for idx in 0..src.len() {
+            let bucket = index >> idx;
+            let parity = (bucket / 2) as usize;
+            xor_into(&mut self.parity_data[parity], &self.old_data[idx]);
+            xor_into(&mut self.parity_data[parity], &src[idx]);
+            xor_into(&mut self.parity_meta[parity], &self.old_meta[idx]);
+            xor_into(&mut self.parity_meta[parity], &src_meta[idx]);
+            self.hashes[bucket as usize] =
+                self.hasher.bucket_hash(bucket, &src[idx], &src_meta[idx]);
+        }
+        self.inner.checkin(index, src, src_meta);
+    }
+    fn prewarm(&mut self) {
+        self.inner.prewarm();
+    }
+}
+
+/// XOR src into dest
+fn xor_into(dest: &mut [u8], src: &[u8]) {
+    for (dest, src) in dest.iter_mut().zip(src.iter()) {
+        *dest ^= *src;
+    }
+}
+
+/// Creates ParityORAMStorage on top of storage from another creator
+pub struct ParityORAMStorageCreator<SC> {
+    _sc: PhantomData<fn() -> SC>,
+}
+
+impl<BlockSize, MetaSize, SC> ORAMStorageCreator<BlockSize, MetaSize>
+    for ParityORAMStorageCreator<SC>
+where
+    BlockSize: ArrayLength<u8> + 'static,
+    MetaSize: ArrayLength<u8> + 'static,
+    SC: ORAMStorageCreator<BlockSize, MetaSize>,
+{
+    type Output = ParityORAMStorage<BlockSize, MetaSize, SC::Output>;
+    type Error = SC::Error;
+
+    fn create<R: RngCore + CryptoRng>(size: u64, rng: &mut R) -> Result<Self::Output, Self::Error> {
+        let inner = SC::create(size, rng)?;
+        Ok(ParityORAMStorage::new(inner, rng))
+    }
+}
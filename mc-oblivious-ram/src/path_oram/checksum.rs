@@ -0,0 +1,101 @@
+//! A running checksum over the contents of the ORAM storage, for detecting
+//! out-of-band tampering with the storage.
+//!
+//! The checksum is the XOR, over every bucket in the tree, of a keyed hash of
+//! the bucket index and its contents. When a branch is checked out, the hashes
+//! of its buckets are XOR'ed out, and when it is checked in, the hashes of the
+//! new contents are XOR'ed in. So maintaining it costs one keyed hash of each
+//! bucket that is read or written, and only depends on the (public) leaf.
+//!
+//! If the storage is modified out-of-band, then the running value no longer
+//! matches a fresh computation over the storage, whether or not the modified
+//! bucket has been read since.
+//!
+//! This module also has the per-block hash used for `PathORAM::digest`, which
+//! is keyed with fixed constants instead, so that digests of different ORAMs
+//! can be compared.
+
+use aligned_cmov::{A64Bytes, A8Bytes, ArrayLength};
+use core::hash::Hasher;
+use rand_core::{CryptoRng, RngCore};
+use siphasher::{
+    sip::SipHasher13,
+    sip128::{Hasher128, SipHasher13 as SipHasher13_128},
+};
+
+/// Fixed keys for the two halves of the 256-bit block digest
+const DIGEST_KEYS: [(u64, u64); 2] = [
+    (0x6d63_2d6f_626c_6976, 0x696f_7573_2d72_616d),
+    (0x6469_6765_7374_2d76, 0x3100_0000_0000_0000),
+];
+
+/// Compute a 256-bit hash of a block number and value, as four u64 words.
+/// This is not keyed with a secret, so it only detects accidental changes.
+pub fn block_digest(block_num: u64, data: &[u8]) -> [u64; 4] {
+    let mut result = [0u64; 4];
+    for (half, (k0, k1)) in DIGEST_KEYS.iter().enumerate() {
+        let mut hasher = SipHasher13_128::new_with_keys(*k0, *k1);
+        hasher.write_u64(block_num);
+        hasher.write(data);
+        let hash = hasher.finish128();
+        result[2 * half] = hash.h1;
+        result[2 * half + 1] = hash.h2;
+    }
+    result
+}
+
+/// A running checksum of the ORAM storage
+pub struct StorageChecksum {
+    k0: u64,
+    k1: u64,
+    value: u64,
+}
+
+impl StorageChecksum {
+    /// Make a new checksum with fresh keys, and a value of zero
+    pub fn new<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        Self {
+            k0: rng.next_u64(),
+            k1: rng.next_u64(),
+            value: 0,
+        }
+    }
+
+    /// The current value of the running checksum
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    /// Overwrite the value of the running checksum
+    pub fn set_value(&mut self, value: u64) {
+        self.value = value;
+    }
+
+    /// Compute the keyed hash of one bucket
+    pub fn bucket_hash(&self, index: u64, data: &[u8], meta: &[u8]) -> u64 {
+        let mut hasher = SipHasher13::new_with_keys(self.k0, self.k1);
+        hasher.write_u64(index);
+        hasher.write(data);
+        hasher.write(meta);
+        hasher.finish()
+    }
+
+    /// Toggle the hashes of all the buckets of a branch in the running value.
+    /// Index 0 of data and meta is the leaf bucket.
+    pub fn toggle_branch<DataSize: ArrayLength<u8>, MetaSize: ArrayLength<u8>>(
+        &mut self,
+        leaf: u64,
+        data: &[A64Bytes<DataSize>],
+        meta: &[A8Bytes<MetaSize>],
+    ) {
+        debug_assert!(data.len() == meta.len());
+        for (idx, (bucket_data, bucket_meta)) in data.iter().zip(meta.iter()).enumerate() {
+            self.toggle_bucket(leaf >> idx, bucket_data, bucket_meta);
+        }
+    }
+
+    /// Toggle the hash of one bucket in the running value
+    pub fn toggle_bucket(&mut self, index: u64, data: &[u8], meta: &[u8]) {
+        self.value ^= self.bucket_hash(index, data, meta);
+    }
+}
@@ -0,0 +1,26 @@
+//! Records of the eviction decisions of a PathORAM, which are passed to a
+//! caller-provided sink, e.g. for building a dataset to train an evictor.
+//!
+//! A record has the branch which was evicted into, which is public, and the
+//! stash occupancy and the loads of the buckets of the branch, which are
+//! aggregates, but are not otherwise revealed by PathORAM. This is meant for
+//! offline experiments, and not for production.
+
+use alloc::{boxed::Box, vec::Vec};
+
+/// A record of one eviction into a branch
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EvictionRecord {
+    /// The leaf of the branch which was evicted into
+    pub leaf: u64,
+    /// The number of occupied stash slots before the eviction
+    pub stash_before: u64,
+    /// The number of occupied stash slots after the eviction
+    pub stash_after: u64,
+    /// The number of occupied slots in each bucket of the branch after the
+    /// eviction, from the root (level 0) down to the leaf bucket
+    pub level_loads: Vec<u64>,
+}
+
+/// A sink for eviction records, see `PathORAM::set_eviction_sink`
+pub type EvictionSink = Box<dyn FnMut(&EvictionRecord) + Send + Sync + 'static>;
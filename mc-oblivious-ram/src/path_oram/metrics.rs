@@ -0,0 +1,78 @@
+//! Aggregate counters about a PathORAM, which can be exported in the
+//! Prometheus text exposition format for monitoring.
+//!
+//! These are only aggregates over all accesses (how many accesses, how many
+//! evictions, the largest stash occupancy seen), and do not identify any
+//! particular query.
+
+use alloc::string::String;
+use core::fmt::Write;
+
+/// Counters maintained by PathORAM when the `metrics` feature is enabled
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    /// Number of accesses performed
+    pub accesses: u64,
+    /// Number of branches that we evicted into
+    pub evictions: u64,
+    /// The largest number of occupied stash slots seen after an access
+    pub max_stash: u64,
+}
+
+impl Metrics {
+    /// Render these counters, and some gauges describing the ORAM,
+    /// in the Prometheus text exposition format.
+    pub fn to_prometheus_text(&self, stash_size: usize, load_factor: f64) -> String {
+        let mut result = String::new();
+        write_metric(
+            &mut result,
+            "accesses_total",
+            "counter",
+            "Number of accesses to the ORAM",
+            self.accesses,
+        );
+        write_metric(
+            &mut result,
+            "evictions_total",
+            "counter",
+            "Number of branches evicted into",
+            self.evictions,
+        );
+        write_metric(
+            &mut result,
+            "max_stash_occupancy",
+            "gauge",
+            "Largest number of occupied stash slots seen after an access",
+            self.max_stash,
+        );
+        write_metric(
+            &mut result,
+            "stash_size",
+            "gauge",
+            "Number of slots in the stash",
+            stash_size,
+        );
+        write_metric(
+            &mut result,
+            "load_factor",
+            "gauge",
+            "Number of values divided by number of slots in the tree",
+            load_factor,
+        );
+        result
+    }
+}
+
+/// Write one metric, with its HELP and TYPE lines
+fn write_metric(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    value: impl core::fmt::Display,
+) {
+    // Writing to a String cannot fail
+    let _ = writeln!(out, "# HELP mc_oblivious_ram_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE mc_oblivious_ram_{} {}", name, kind);
+    let _ = writeln!(out, "mc_oblivious_ram_{} {}", name, value);
+}
@@ -14,14 +14,39 @@
 
 use alloc::vec;
 
+mod checksum;
+pub(crate) use checksum::StorageChecksum;
+
+mod scramble;
+use scramble::KeyScrambler;
+
+mod trace;
+pub use trace::TraceAccess;
+use trace::{ReplayLeafAssigner, ReplayPositionMap};
+
+#[cfg(feature = "metrics")]
+mod metrics;
+
+#[cfg(feature = "eviction_log")]
+mod eviction_log;
+#[cfg(feature = "eviction_log")]
+pub use eviction_log::{EvictionRecord, EvictionSink};
+
+use crate::{EvictionStrategy, ItemFirstEvictionStrategy, LeafAssigner, UniformLeafAssigner};
 use aligned_cmov::{
+    cswap,
     subtle::{Choice, ConstantTimeEq, ConstantTimeLess},
     typenum::{PartialDiv, Prod, Unsigned, U16, U64, U8},
     A64Bytes, A8Bytes, ArrayLength, AsAlignedChunks, AsNeSlice, CMov,
 };
 use alloc::{boxed::Box, vec::Vec};
 use balanced_tree_index::TreeIndex;
-use core::{marker::PhantomData, ops::Mul};
+use core::{
+    fmt,
+    marker::PhantomData,
+    ops::{Mul, Range},
+    str::FromStr,
+};
 use mc_oblivious_traits::{
     log2_ceil, ORAMStorage, ORAMStorageCreator, PositionMap, PositionMapCreator, ORAM,
 };
@@ -32,7 +57,7 @@ use rand_core::{CryptoRng, RngCore};
 /// It is stored separately from the value so as not to break alignment.
 /// In many cases block-num and leaf can be u32's. But I suspect that there will
 /// be other stuff in this metadata as well in the end so the savings isn't much.
-type MetaSize = U16;
+pub type MetaSize = U16;
 
 // A metadata object is always associated to any Value in the PathORAM structure.
 // A metadata consists of two fields: leaf_num and block_num
@@ -51,31 +76,224 @@ type MetaSize = U16;
 //
 // A metadata is defined to be "vacant" if leaf_num IS zero.
 // This indicates that the metadata and its corresponding value can be overwritten
-// with a real item.
+// with a real item. When an item is removed, its whole metadata is zeroed, so a
+// vacant metadata is always all zeroes.
+//
+// The highest bit of the block_num field is the "pin" bit, which is not part of
+// the block number. A pinned item is never evicted from the stash.
 
 /// Get the leaf num of a metadata
-fn meta_leaf_num(src: &A8Bytes<MetaSize>) -> &u64 {
+pub fn meta_leaf_num(src: &A8Bytes<MetaSize>) -> &u64 {
     &src.as_ne_u64_slice()[0]
 }
 /// Get the leaf num of a mutable metadata
-fn meta_leaf_num_mut(src: &mut A8Bytes<MetaSize>) -> &mut u64 {
+pub fn meta_leaf_num_mut(src: &mut A8Bytes<MetaSize>) -> &mut u64 {
     &mut src.as_mut_ne_u64_slice()[0]
 }
-/// Get the block num of a metadata
-fn meta_block_num(src: &A8Bytes<MetaSize>) -> &u64 {
-    &src.as_ne_u64_slice()[1]
+/// The bit of the block num field which marks an item as pinned to the stash
+const PIN_BIT: u64 = 1 << 63;
+
+/// Get the block num of a metadata (excluding the pin bit)
+pub fn meta_block_num(src: &A8Bytes<MetaSize>) -> u64 {
+    src.as_ne_u64_slice()[1] & !PIN_BIT
 }
-/// Get the block num of a mutable metadata
-fn meta_block_num_mut(src: &mut A8Bytes<MetaSize>) -> &mut u64 {
+/// Get the block num field of a mutable metadata (including the pin bit)
+pub(crate) fn meta_block_num_mut(src: &mut A8Bytes<MetaSize>) -> &mut u64 {
     &mut src.as_mut_ne_u64_slice()[1]
 }
+/// Set the block num of a metadata (keeping the pin bit), obliviously, if a
+/// condition is true
+pub fn meta_set_block_num_if(condition: Choice, src: &mut A8Bytes<MetaSize>, block_num: u64) {
+    debug_assert!(block_num & PIN_BIT == 0, "block num overlaps the pin bit");
+    let field = meta_block_num_mut(src);
+    let new_val = (*field & PIN_BIT) | block_num;
+    field.cmov(condition, &new_val);
+}
+/// Test if a metadata is pinned to the stash
+pub fn meta_is_pinned(src: &A8Bytes<MetaSize>) -> Choice {
+    (src.as_ne_u64_slice()[1] & PIN_BIT).ct_eq(&PIN_BIT)
+}
 /// Test if a metadata is "vacant"
-fn meta_is_vacant(src: &A8Bytes<MetaSize>) -> Choice {
+pub fn meta_is_vacant(src: &A8Bytes<MetaSize>) -> Choice {
     meta_leaf_num(src).ct_eq(&0)
 }
-/// Set a metadata to vacant, obliviously, if a condition is true
-fn meta_set_vacant(condition: Choice, src: &mut A8Bytes<MetaSize>) {
-    meta_leaf_num_mut(src).cmov(condition, &0);
+/// Set a metadata to vacant, obliviously, if a condition is true.
+/// The whole metadata is zeroed, including the block num and pin bit.
+pub fn meta_set_vacant(condition: Choice, src: &mut A8Bytes<MetaSize>) {
+    src.cmov(condition, &Default::default());
+}
+
+/// Overwrite a whole metadata with another, obliviously, if a condition is true.
+///
+/// The source must be either live, or in the canonical vacant encoding (all
+/// zeroes). A "half-vacant" source, with a leaf num of zero but a nonzero
+/// block num field, is rejected in debug builds, regardless of the condition.
+pub fn set_meta_if(condition: Choice, dest: &mut A8Bytes<MetaSize>, src: &A8Bytes<MetaSize>) {
+    debug_assert!(
+        !bool::from(meta_is_vacant(src)) || meta_block_num_field(src) == 0,
+        "metadata is half-vacant"
+    );
+    dest.cmov(condition, src);
+}
+/// Get the block num field of a metadata (including the pin bit)
+fn meta_block_num_field(src: &A8Bytes<MetaSize>) -> u64 {
+    src.as_ne_u64_slice()[1]
+}
+
+/// Set every slot of a bucket's metadata to the canonical vacant encoding,
+/// all zeroes, which is also what meta_set_vacant produces, e.g. when building
+/// buckets by hand in tests and tools.
+pub fn init_vacant<Z>(bucket_meta: &mut A8Bytes<Prod<Z, MetaSize>>)
+where
+    Z: Unsigned + Mul<MetaSize>,
+    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
+{
+    *bucket_meta = Default::default();
+}
+/// Test if any slot of a bucket's metadata is vacant, in constant time
+pub fn bucket_has_empty_slot<Z>(bucket_meta: &A8Bytes<Prod<Z, MetaSize>>) -> Choice
+where
+    Z: Unsigned + Mul<MetaSize>,
+    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
+{
+    let slots: &[A8Bytes<MetaSize>] = bucket_meta.as_aligned_chunks();
+    slots
+        .iter()
+        .fold(Choice::from(0), |acc, meta| acc | meta_is_vacant(meta))
+}
+
+/// An error which can occur when accessing PathORAM with `try_access`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ORAMError {
+    /// The key was out of bounds for this ORAM
+    KeyOutOfBounds,
+    /// The position map returned a leaf which is not a leaf of our tree,
+    /// so the position map is inconsistent with the storage.
+    InvalidPosition,
+    /// The stash was full, so the accessed block was kept in the spill area
+    /// instead. See `OverflowPolicy::ReturnError`.
+    StashOverflow,
+    /// A branch which was checked in did not read back as it was written, so
+    /// the storage is faulty. See `enable_write_verification`.
+    WriteVerificationFailed,
+}
+
+impl fmt::Display for ORAMError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ORAMError::KeyOutOfBounds => write!(formatter, "Key out of bounds"),
+            ORAMError::InvalidPosition => {
+                write!(formatter, "Position map returned an invalid leaf")
+            }
+            ORAMError::StashOverflow => write!(formatter, "Stash overflow"),
+            ORAMError::WriteVerificationFailed => {
+                write!(formatter, "Storage did not retain a checked-in branch")
+            }
+        }
+    }
+}
+
+/// How the branches for extra eviction rounds are chosen.
+/// See `PathORAM::set_eviction_rounds`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BranchSelector {
+    /// Take leaves in reverse-lexicographic order. This is the default.
+    ReverseLex,
+    /// Take a leaf under the top-level subtree which has been evicted into the
+    /// least so far, counting every eviction, including those of accessed
+    /// branches. Within that subtree, leaves are taken in reverse-lexicographic
+    /// order. This evens out eviction when accessed branches are skewed, e.g.
+    /// by a `SubtreeLeafAssigner`.
+    StalestSubtree,
+    /// Take leaves from left to right, which is the order a depth-first
+    /// traversal of the tree visits them. Every leaf under a subtree is taken
+    /// before any leaf to its right, which concentrates eviction in one part of
+    /// the tree at a time. Like `ReverseLex`, every leaf is taken exactly once
+    /// per 2^height extra rounds.
+    DepthFirst,
+}
+
+impl BranchSelector {
+    /// All of the available branch selectors
+    pub const ALL: [BranchSelector; 3] = [
+        BranchSelector::ReverseLex,
+        BranchSelector::StalestSubtree,
+        BranchSelector::DepthFirst,
+    ];
+
+    /// The name of this selector, as accepted by `FromStr`
+    pub fn name(&self) -> &'static str {
+        match self {
+            BranchSelector::ReverseLex => "reverse-lex",
+            BranchSelector::StalestSubtree => "stalest-subtree",
+            BranchSelector::DepthFirst => "depth-first",
+        }
+    }
+}
+
+impl fmt::Display for BranchSelector {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name())
+    }
+}
+
+/// The error returned when parsing an unknown branch selector name
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct UnknownBranchSelector;
+
+impl fmt::Display for UnknownBranchSelector {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "Unknown branch selector")
+    }
+}
+
+impl FromStr for BranchSelector {
+    type Err = UnknownBranchSelector;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|selector| selector.name() == src)
+            .copied()
+            .ok_or(UnknownBranchSelector)
+    }
+}
+
+/// The level of the tree whose subtrees `BranchSelector::StalestSubtree`
+/// balances eviction across (or the leaves, in smaller trees)
+const SELECTOR_SUBTREE_LEVEL: u32 = 3;
+
+/// Where an accessed block is placed in the stash, among the vacant slots.
+/// Either way, every slot is visited, so the choice of slot is oblivious.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StashPlacement {
+    /// Take the first vacant slot. This is the default.
+    FrontFill,
+    /// Take the first vacant slot at or after a starting slot, wrapping around.
+    /// The starting slot advances by one on each access, so that blocks are
+    /// spread across the stash instead of clustering at the front.
+    Spread,
+}
+
+/// What PathORAM does when the stash is full, and an accessed block cannot be
+/// put back into it.
+///
+/// Stash overflow happens with negligible probability when the stash is sized
+/// appropriately. When it does happen, it is revealed by any of these policies.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// Panic. This is the default.
+    Panic,
+    /// Finish the access, keeping the accessed block in the spill area as for
+    /// `SpillToSecondary`, and return `ORAMError::StashOverflow`. The block,
+    /// including any change made by the access, is not lost, but the result
+    /// of the callback is. Note that `ORAM::access` panics on this error, so
+    /// `try_access` should be used instead.
+    ReturnError,
+    /// Keep the accessed block in a secondary spill area, which grows as needed,
+    /// and which is scanned on every access. Spilled blocks move back into the
+    /// stash as space frees up.
+    SpillToSecondary,
 }
 
 /// An implementation of PathORAM, using u64 to represent leaves in metadata.
@@ -96,12 +314,52 @@ where
     pos: Box<dyn PositionMap + Send + Sync + 'static>,
     /// The rng
     rng: RngType,
+    /// The strategy for choosing new leaves for accessed blocks
+    leaf_assigner: Box<dyn LeafAssigner<RngType> + Send + Sync + 'static>,
     /// The stashed values
     stash_data: Vec<A64Bytes<ValueSize>>,
     /// The stashed metadata
     stash_meta: Vec<A8Bytes<MetaSize>>,
+    /// The permutation applied to keys before they are used, if enabled
+    key_scrambler: Option<KeyScrambler>,
+    /// Whether any access has been made, after which keys can't be scrambled
+    accessed: bool,
+    /// Where accessed blocks are placed in the stash
+    stash_placement: StashPlacement,
+    /// The number of accesses which placed a block with `StashPlacement::Spread`
+    stash_placement_counter: u64,
+    /// What to do when the stash overflows
+    overflow_policy: OverflowPolicy,
+    /// Blocks which overflowed the stash, unless `OverflowPolicy::Panic` is used
+    spill_data: Vec<A64Bytes<ValueSize>>,
+    /// The metadata of blocks which overflowed the stash
+    spill_meta: Vec<A8Bytes<MetaSize>>,
     /// Our currently checked-out branch if any
     branch: BranchCheckout<ValueSize, Z>,
+    /// The level at which we flip the accessed branch to get a sibling branch,
+    /// which is also evicted after each access. None if this is disabled.
+    sibling_eviction_level: Option<u32>,
+    /// The procedure used to evict from the stash into a branch
+    eviction_strategy: Box<dyn EvictionStrategy<ValueSize, Z>>,
+    /// The number of branches which we evict into (full stash scans) per access
+    eviction_rounds: u32,
+    /// The number of extra branches evicted so far, which determines the next
+    /// one in reverse-lexicographic order
+    reverse_lex_counter: u64,
+    /// How the branches for extra eviction rounds are chosen
+    branch_selector: BranchSelector,
+    /// The number of evictions into each subtree at SELECTOR_SUBTREE_LEVEL.
+    /// This depends only on which branches were evicted, which is public.
+    subtree_evictions: Vec<u64>,
+    /// Aggregate counters for monitoring
+    #[cfg(feature = "metrics")]
+    metrics: metrics::Metrics,
+    /// Whether the accessed branch stays checked out after an access, see
+    /// `set_checkout_reuse`
+    reuse_checkout: bool,
+    /// Where to send a record of each eviction, if anywhere
+    #[cfg(feature = "eviction_log")]
+    eviction_sink: Option<EvictionSink>,
 }
 
 impl<ValueSize, Z, StorageType, RngType> PathORAM<ValueSize, Z, StorageType, RngType>
@@ -118,6 +376,9 @@ where
     /// The main thing that is going on here is, given the size, we are determining
     /// what the height will be, which will be like log(size) - log(bucket_size)
     /// Then we are making sure that all the various creators use this number.
+    ///
+    /// The size must be a nonzero power of two. Sizes up to the bucket size
+    /// are fine, and give a tree which is just a root bucket.
     pub fn new<
         PMC: PositionMapCreator<RngType>,
         SC: ORAMStorageCreator<Prod<Z, ValueSize>, Prod<Z, MetaSize>, Output = StorageType>,
@@ -129,52 +390,308 @@ where
     ) -> Self {
         assert!(size != 0, "size cannot be zero");
         assert!(size & (size - 1) == 0, "size must be a power of two");
-        // saturating_sub is used so that creating an ORAM of size 1 or 2 doesn't fail
-        let height = log2_ceil(size).saturating_sub(log2_ceil(Z::U64));
+        let height = Self::height_for_size(size);
         // This is 2u64 << height because it must be 2^{h+1}, we have defined
         // the height of the root to be 0, so in a tree where the lowest level
         // is h, there are 2^{h+1} nodes.
         let mut rng = rng_maker();
         let storage = SC::create(2u64 << height, &mut rng).expect("Storage failed");
         let pos = PMC::create(size, height, stash_size, rng_maker);
+        Self::from_parts(height, storage, pos, rng, stash_size)
+    }
+
+    /// Create this ORAM like `new`, but with a given policy for what happens
+    /// when the stash overflows, instead of `OverflowPolicy::Panic`.
+    pub fn new_with_overflow_policy<
+        PMC: PositionMapCreator<RngType>,
+        SC: ORAMStorageCreator<Prod<Z, ValueSize>, Prod<Z, MetaSize>, Output = StorageType>,
+        F: FnMut() -> RngType + 'static,
+    >(
+        size: u64,
+        stash_size: usize,
+        overflow_policy: OverflowPolicy,
+        rng_maker: &mut F,
+    ) -> Self {
+        let mut result = Self::new::<PMC, SC, F>(size, stash_size, rng_maker);
+        result.overflow_policy = overflow_policy;
+        result
+    }
+
+    /// Create this ORAM given a position map object, a storage type creator
+    /// and an Rng creator.
+    ///
+    /// This allows the position map to be any backend that implements the
+    /// PositionMap trait, for example one which is not itself an ORAM.
+    /// The size of the ORAM is the length of the position map, and the position
+    /// map must produce leaves at the height returned by `height_for_size`.
+    /// `TrivialPositionMap` is a simple in-memory implementation.
+    pub fn new_with_position_map<
+        SC: ORAMStorageCreator<Prod<Z, ValueSize>, Prod<Z, MetaSize>, Output = StorageType>,
+        F: FnMut() -> RngType + 'static,
+    >(
+        pos: Box<dyn PositionMap + Send + Sync + 'static>,
+        stash_size: usize,
+        rng_maker: &mut F,
+    ) -> Self {
+        let size = pos.len();
+        assert!(size != 0, "size cannot be zero");
+        assert!(size & (size - 1) == 0, "size must be a power of two");
+        let height = Self::height_for_size(size);
+        let mut rng = rng_maker();
+        let storage = SC::create(2u64 << height, &mut rng).expect("Storage failed");
+        Self::from_parts(height, storage, pos, rng, stash_size)
+    }
+
+    /// The height of the tree that is used for an ORAM of a given size.
+    /// This is log(size) - log(bucket_size), and at least zero.
+    pub fn height_for_size(size: u64) -> u32 {
+        // saturating_sub is used so that creating an ORAM of size 1 or 2 doesn't fail
+        log2_ceil(size).saturating_sub(log2_ceil(Z::U64))
+    }
+
+    /// The height of the tree of this ORAM. See `height_for_size`.
+    /// This is structural, and not secret.
+    pub fn tree_height(&self) -> u32 {
+        self.height
+    }
+
+    /// The number of values in each bucket of the tree (Z)
+    pub fn bucket_size(&self) -> u32 {
+        Z::U32
+    }
+
+    /// The number of leaves of the tree of this ORAM, which is 2^height
+    pub fn leaf_count(&self) -> u64 {
+        1u64 << self.height
+    }
+
+    /// The number of slots in the stash, as given at construction. This does
+    /// not count the spill area, see `spill_len`.
+    pub fn stash_size(&self) -> usize {
+        self.stash_data.len()
+    }
+
+    /// The number of blocks in the spill area, which holds blocks that
+    /// overflowed the stash, see `OverflowPolicy`. This is zero unless the
+    /// stash has overflowed, which is revealed anyways.
+    pub fn spill_len(&self) -> usize {
+        self.spill_meta.len()
+    }
+
+    /// Check the invariants about the sizes of values and buckets, which make
+    /// splitting a bucket into aligned chunks valid:
+    /// - Each value is a multiple of 64 bytes, so that A64Bytes chunks of a
+    ///   bucket stay 64-byte aligned
+    /// - The data of a bucket is exactly Z values, and the metadata exactly Z
+    ///   metadata, which is a multiple of 8 bytes
+    ///
+    /// The trait bounds on PathORAM imply these, but we check them loudly
+    /// in case the bounds are loosened.
+    fn assert_layout() {
+        assert!(
+            ValueSize::USIZE != 0 && ValueSize::USIZE % 64 == 0,
+            "ValueSize must be a nonzero multiple of 64 bytes"
+        );
+        assert!(Z::USIZE != 0, "Z must be nonzero");
+        assert_eq!(
+            <Prod<Z, ValueSize>>::USIZE,
+            Z::USIZE * ValueSize::USIZE,
+            "bucket data size must be Z * ValueSize"
+        );
+        assert_eq!(
+            <Prod<Z, MetaSize>>::USIZE,
+            Z::USIZE * MetaSize::USIZE,
+            "bucket metadata size must be Z * MetaSize"
+        );
+        assert_eq!(
+            <Prod<Z, MetaSize>>::USIZE % 8,
+            0,
+            "bucket metadata size must be a multiple of 8 bytes"
+        );
+    }
+
+    /// Assemble the ORAM from its parts, with default settings
+    fn from_parts(
+        height: u32,
+        storage: StorageType,
+        pos: Box<dyn PositionMap + Send + Sync + 'static>,
+        rng: RngType,
+        stash_size: usize,
+    ) -> Self {
+        Self::assert_layout();
+        // The tree has 2^{h+1} - 1 buckets, and this count must fit in a u64.
+        assert!(height < 63, "tree height {} is too large", height);
         Self {
             height,
             storage,
             pos,
             rng,
+            leaf_assigner: Box::new(UniformLeafAssigner),
             stash_data: vec![Default::default(); stash_size],
             stash_meta: vec![Default::default(); stash_size],
+            key_scrambler: None,
+            accessed: false,
+            stash_placement: StashPlacement::FrontFill,
+            stash_placement_counter: 0,
+            overflow_policy: OverflowPolicy::Panic,
+            spill_data: Vec::new(),
+            spill_meta: Vec::new(),
             branch: Default::default(),
+            sibling_eviction_level: None,
+            eviction_strategy: Box::new(ItemFirstEvictionStrategy),
+            eviction_rounds: 1,
+            reverse_lex_counter: 0,
+            branch_selector: BranchSelector::ReverseLex,
+            subtree_evictions: vec![0; 1 << height.min(SELECTOR_SUBTREE_LEVEL)],
+            #[cfg(feature = "metrics")]
+            metrics: Default::default(),
+            reuse_checkout: false,
+            #[cfg(feature = "eviction_log")]
+            eviction_sink: None,
         }
     }
-}
 
-impl<ValueSize, Z, StorageType, RngType> ORAM<ValueSize>
-    for PathORAM<ValueSize, Z, StorageType, RngType>
-where
-    ValueSize: ArrayLength<u8> + PartialDiv<U8> + PartialDiv<U64>,
-    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
-    RngType: RngCore + CryptoRng + Send + Sync + 'static,
-    StorageType: ORAMStorage<Prod<Z, ValueSize>, Prod<Z, MetaSize>> + Send + Sync + 'static,
-    Prod<Z, ValueSize>: ArrayLength<u8> + PartialDiv<U8>,
-    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
-{
-    fn len(&self) -> u64 {
-        self.pos.len()
+    /// Access the ORAM at a position, calling a lambda with the recovered value,
+    /// and returning the result of the lambda.
+    ///
+    /// Unlike `ORAM::access`, this does not panic if the key is out of bounds,
+    /// or if the position map gives us a leaf that is not valid for this tree
+    /// (e.g. because it is inconsistent after a partial restore). In that case,
+    /// an error is returned, the lambda is not called, and the position map
+    /// entry for this key is replaced with a fresh random leaf.
+    ///
+    /// Returning an error reveals that the position map was inconsistent, but
+    /// the leaf we look up is revealed by the access pattern anyways.
+    pub fn try_access<T, F: FnOnce(&mut A64Bytes<ValueSize>) -> T>(
+        &mut self,
+        key: u64,
+        f: F,
+    ) -> Result<T, ORAMError> {
+        self.access_impl(key, None, self.eviction_rounds, true, |data, _| f(data))
     }
-    // TODO: We should try implementing a circuit-ORAM like approach also
-    fn access<T, F: FnOnce(&mut A64Bytes<ValueSize>) -> T>(&mut self, key: u64, f: F) -> T {
+
+    /// Like `try_access`, but overriding the number of eviction rounds for this
+    /// access only. See `set_eviction_rounds`.
+    ///
+    /// This lets a caller burst eviction, e.g. during idle time, to drain the
+    /// stash faster. The number of rounds is not secret, and the extra branches
+    /// are chosen the same way as usual.
+    pub fn try_access_with_eviction_rounds<T, F: FnOnce(&mut A64Bytes<ValueSize>) -> T>(
+        &mut self,
+        key: u64,
+        eviction_rounds: u32,
+        f: F,
+    ) -> Result<T, ORAMError> {
+        assert!(
+            eviction_rounds >= 1,
+            "there must be at least one eviction round"
+        );
+        self.access_impl(key, None, eviction_rounds, true, |data, _| f(data))
+    }
+
+    /// Insert a value for a key, or update it in place if the key exists.
+    ///
+    /// If the key already holds a block, `on_exists` is applied to its value.
+    /// Otherwise, the value becomes `value`. Both the closure and the copy are
+    /// always performed, and the result is selected in constant time, so the
+    /// access pattern is identical in both cases. For this to be oblivious,
+    /// `on_exists` itself must be constant-time.
+    ///
+    /// Returns whether the key already existed. A key exists once it has been
+    /// written, e.g. by `write`, `access` or `access_pair`, and until it is
+    /// deleted by `delete_where`. Reads such as `read`, `access_ref` and
+    /// `dummy_access` leave a key which doesn't exist as it is.
+    pub fn upsert(
+        &mut self,
+        key: u64,
+        value: &A64Bytes<ValueSize>,
+        on_exists: impl FnOnce(&mut A64Bytes<ValueSize>),
+    ) -> Result<Choice, ORAMError> {
+        self.access_impl(key, None, self.eviction_rounds, true, |data, found| {
+            let mut updated = data.clone();
+            on_exists(&mut updated);
+            *data = value.clone();
+            data.cmov(found, &updated);
+            found
+        })
+    }
+
+    /// Do a full access, including eviction, at a key chosen uniformly at
+    /// random, without changing any value.
+    ///
+    /// This touches storage in the same way as any real access, so that a
+    /// protocol step which does nothing can't be told apart from one which
+    /// accesses the ORAM. If the chosen key was never written, it stays vacant,
+    /// so e.g. `upsert` still sees it as not existing.
+    pub fn dummy_access(&mut self) {
+        let key = self.rng.next_u64() % self.pos.len();
+        self.access_impl(key, None, self.eviction_rounds, false, |_, _| ())
+            .expect("a random key is in bounds");
+    }
+
+    /// Pin or unpin a key to the stash.
+    ///
+    /// A pinned item is never evicted from the stash into the tree, so it is
+    /// always found in the stash. This is done obliviously: the eviction pass
+    /// still does the same work for pinned items, it just never moves them.
+    /// Unpinning an item lets it drain from the stash normally.
+    ///
+    /// This performs an ordinary access to the key, which is indistinguishable
+    /// from any other access. Pinned items take up stash space permanently,
+    /// so only a small number of keys should be pinned, or the stash will
+    /// overflow.
+    pub fn set_pinned(&mut self, key: u64, pinned: bool) -> Result<(), ORAMError> {
+        self.access_impl(key, Some(pinned), self.eviction_rounds, true, |_, _| ())
+    }
+
+    /// The implementation of access. If pin is Some, then the pin bit of the
+    /// item is set to that value, otherwise it is left as is.
+    /// The given number of eviction rounds is done after the access.
+    /// The callback is also told whether the item was found in the branch or
+    /// the stash, as opposed to being vacant (never written, or deleted).
+    /// If create is false, an item which was not found stays vacant, whatever
+    /// the callback does, otherwise it is stored like any other.
+    fn access_impl<T, F: FnOnce(&mut A64Bytes<ValueSize>, Choice) -> T>(
+        &mut self,
+        key: u64,
+        pin: Option<bool>,
+        eviction_rounds: u32,
+        create: bool,
+        f: F,
+    ) -> Result<T, ORAMError> {
+        if key >= self.pos.len() {
+            return Err(ORAMError::KeyOutOfBounds);
+        }
+        self.accessed = true;
+        let key = self.scramble_key(key);
+        // Only public, structural values may be recorded in spans
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!("oram_access", height = self.height);
+        #[cfg(feature = "tracing")]
+        let _guard = span.enter();
         let result: T;
+        let mut overflowed = false;
         // Choose what will be the next (secret) position of this item
-        let new_pos = 1u64.random_child_at_height(self.height, &mut self.rng);
+        let new_pos = self.leaf_assigner.assign_leaf(self.height, &mut self.rng);
+        debug_assert!(
+            new_pos >> self.height == 1,
+            "leaf assigner returned a value which is not a leaf"
+        );
         // Set the new value and recover the old (current) position.
         let current_pos = self.pos.write(&key, &new_pos);
         debug_assert!(current_pos != 0, "position map told us the item is at 0");
+        // The current position must be a leaf of our tree
+        if current_pos >> self.height != 1 {
+            return Err(ORAMError::InvalidPosition);
+        }
+
         // Get the branch where we expect to find the item.
         // NOTE: If we move to a scheme where the tree can be resized dynamically,
         // then we should checkout at `current_pos.random_child_at_height(self.height)`.
-        debug_assert!(self.branch.leaf == 0);
-        self.branch.checkout(&mut self.storage, current_pos);
+        if self.branch.leaf != current_pos {
+            self.release_checkout();
+            self.branch.checkout(&mut self.storage, current_pos);
+        }
 
         // Fetch the item from branch and then from stash.
         // Visit it and then insert it into the stash.
@@ -193,99 +710,863 @@ where
                 &mut self.stash_data,
                 &mut self.stash_meta,
             );
+            details::ct_find_and_remove(
+                1.into(),
+                &key,
+                &mut data,
+                &mut meta,
+                &mut self.spill_data,
+                &mut self.spill_meta,
+            );
             debug_assert!(
-                meta_block_num(&meta) == &key || meta_is_vacant(&meta).into(),
+                meta_block_num(&meta) == key || meta_is_vacant(&meta).into(),
                 "Hmm, we didn't find the expected item something else"
             );
             debug_assert!(self.branch.leaf == current_pos);
 
             // Call the callback, then store the result
-            result = f(&mut data);
+            let found = !meta_is_vacant(&meta);
+            result = f(&mut data, found);
+            // Whether the item is live after this access. A vacant meta is
+            // not inserted into the stash below, so the work is the same.
+            let live = found | Choice::from(create as u8);
 
-            // Set the block_num in case the item was not initialized yet
-            *meta_block_num_mut(&mut meta) = key;
+            // Set the block_num in case the item was not initialized yet,
+            // keeping the pin bit unless we were asked to change it
+            meta_set_block_num_if(live, &mut meta, key);
+            if let Some(pinned) = pin {
+                let pin_bit = if pinned { PIN_BIT } else { 0 };
+                *meta_block_num_mut(&mut meta) = key | pin_bit;
+            }
             // Set the new leaf destination for the item
-            *meta_leaf_num_mut(&mut meta) = new_pos;
+            meta_leaf_num_mut(&mut meta).cmov(live, &new_pos);
 
             // Stash the item
-            details::ct_insert(
+            let start = self.next_stash_start();
+            details::ct_insert_from(
+                start,
                 1.into(),
                 &data,
                 &mut meta,
                 &mut self.stash_data,
                 &mut self.stash_meta,
             );
-            assert!(bool::from(meta_is_vacant(&meta)), "Stash overflow!");
+            if !bool::from(meta_is_vacant(&meta)) {
+                match self.overflow_policy {
+                    OverflowPolicy::Panic => panic!("Stash overflow!"),
+                    OverflowPolicy::ReturnError | OverflowPolicy::SpillToSecondary => {
+                        overflowed = self.overflow_policy == OverflowPolicy::ReturnError;
+                        self.spill_data.push(data);
+                        self.spill_meta.push(meta);
+                    }
+                }
+            }
         }
 
         // Now do cleanup / eviction on this branch, before checking out
+        debug_assert!(self.branch.leaf == current_pos);
+        self.evict_into_branch();
+
+        // The branch is only kept if no other branch is evicted into next
+        debug_assert!(self.branch.leaf == current_pos);
+        debug_assert!(!self.reuse_checkout || self.sibling_eviction_level.is_none());
+        if !self.reuse_checkout || eviction_rounds > 1 {
+            self.branch.checkin(&mut self.storage);
+            debug_assert!(self.branch.leaf == 0);
+        }
+
+        // If configured, also evict the sibling branch at the chosen level
+        if let Some(level) = self.sibling_eviction_level {
+            self.evict_branch(sibling_leaf(current_pos, level, self.height));
+        }
+
+        // If configured, do more eviction rounds, into public branches
+        for _ in 1..eviction_rounds {
+            let leaf = self.next_extra_leaf();
+            self.evict_branch(leaf);
+        }
+
+        // If anything was spilled, try to move it back into the stash
+        if !self.spill_meta.is_empty() {
+            self.unspill();
+        }
+
+        #[cfg(feature = "metrics")]
         {
-            debug_assert!(self.branch.leaf == current_pos);
-            self.branch.pack();
-            for idx in 0..self.stash_data.len() {
-                self.branch
-                    .ct_insert(1.into(), &self.stash_data[idx], &mut self.stash_meta[idx]);
+            self.metrics.accesses += 1;
+            let stash_occupancy = details::ct_count_occupied(&self.stash_meta);
+            if stash_occupancy > self.metrics.max_stash {
+                self.metrics.max_stash = stash_occupancy;
             }
         }
 
-        debug_assert!(self.branch.leaf == current_pos);
-        self.branch.checkin(&mut self.storage);
+        if core::mem::take(&mut self.branch.write_fault) {
+            return Err(ORAMError::WriteVerificationFailed);
+        }
+        if overflowed {
+            return Err(ORAMError::StashOverflow);
+        }
+        Ok(result)
+    }
+
+    /// Move as many spilled blocks as fit back into the stash, and shrink the
+    /// spill area. The size of the spill area is revealed by this, but it is
+    /// only nonempty after a stash overflow, which is revealed anyways.
+    fn unspill(&mut self) {
+        for idx in 0..self.spill_data.len() {
+            details::ct_insert(
+                1.into(),
+                &self.spill_data[idx],
+                &mut self.spill_meta[idx],
+                &mut self.stash_data,
+                &mut self.stash_meta,
+            );
+        }
+        let mut idx = 0;
+        while idx < self.spill_meta.len() {
+            if bool::from(meta_is_vacant(&self.spill_meta[idx])) {
+                self.spill_data.swap_remove(idx);
+                self.spill_meta.swap_remove(idx);
+            } else {
+                idx += 1;
+            }
+        }
+    }
+
+    /// Delete every block for which a predicate holds, by marking it vacant.
+    ///
+    /// The predicate is called with the block number and value of every
+    /// occupied slot in the tree and the stash. This is done in a single pass
+    /// over every bucket of the tree (in a fixed order, see
+    /// `ORAMStorage::for_each_bucket`) and the stash, so the access pattern
+    /// does not reveal how many blocks matched, or which.
+    /// For this to be oblivious, the predicate itself must be constant-time.
+    ///
+    /// Deleted blocks read as zeroes afterwards, as if never written, and their
+    /// values are also cleared from the slots they occupied.
+    pub fn delete_where(&mut self, pred: impl Fn(u64, &A64Bytes<ValueSize>) -> bool) {
+        self.release_checkout();
+        let key_scrambler = self.key_scrambler.clone();
+        let pred = |block_num: u64, data: &A64Bytes<ValueSize>| {
+            pred(unscramble(&key_scrambler, block_num), data)
+        };
+        // Keep the running checksum, if any, up to date with each bucket
+        let mut checksum = self.branch.checksum.as_mut();
+        self.storage.for_each_bucket(&mut |index, data, meta| {
+            if let Some(checksum) = checksum.as_mut() {
+                checksum.toggle_bucket(index, data, meta);
+            }
+            let bucket_data: &mut [A64Bytes<ValueSize>] = data.as_mut_aligned_chunks();
+            let bucket_meta: &mut [A8Bytes<MetaSize>] = meta.as_mut_aligned_chunks();
+            details::ct_delete_where(&pred, bucket_data, bucket_meta);
+            if let Some(checksum) = checksum.as_mut() {
+                checksum.toggle_bucket(index, data, meta);
+            }
+        });
+        details::ct_delete_where(&pred, &mut self.stash_data, &mut self.stash_meta);
+        details::ct_delete_where(&pred, &mut self.spill_data, &mut self.spill_meta);
         debug_assert!(self.branch.leaf == 0);
+    }
+
+    /// Export aggregate counters about this ORAM (accesses, evictions, max
+    /// stash occupancy, load factor) in the Prometheus text exposition format.
+    ///
+    /// These are aggregates over all accesses, and are not secret.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_text(&self) -> alloc::string::String {
+        // The tree has 2^{h+1} - 1 buckets, each of which has Z slots
+        let num_slots = ((2u64 << self.height) - 1) * Z::U64;
+        let load_factor = self.pos.len() as f64 / num_slots as f64;
+        self.metrics
+            .to_prometheus_text(self.stash_data.len(), load_factor)
+    }
+
+    /// Send a record of each eviction, with the branch evicted into, and the
+    /// stash occupancy and bucket loads, to a sink, or stop if None.
+    ///
+    /// This is for building datasets offline, and is NOT oblivious: the sink
+    /// learns the stash occupancy and the loads of each evicted branch.
+    #[cfg(feature = "eviction_log")]
+    pub fn set_eviction_sink(&mut self, sink: Option<EvictionSink>) {
+        self.eviction_sink = sink;
+    }
+
+    /// List the (block_num, leaf_num) of every occupied stash slot.
+    ///
+    /// This is a diagnostic for unexpected stash growth, and is NOT oblivious:
+    /// the result reveals the secret positions of the stashed blocks.
+    #[cfg(feature = "debug_stash")]
+    pub fn debug_stash_snapshot(&self) -> Vec<(u64, u64)> {
+        self.stash_meta
+            .iter()
+            .filter(|meta| !bool::from(meta_is_vacant(meta)))
+            .map(|meta| {
+                let block_num = unscramble(&self.key_scrambler, meta_block_num(meta));
+                (block_num, *meta_leaf_num(meta))
+            })
+            .collect()
+    }
 
+    /// Render the branch to a given leaf as a human-readable tree, from the root
+    /// bucket down to the leaf bucket, listing the block number and leaf of
+    /// each occupied slot. Pinned blocks are marked with `*`, vacant slots
+    /// are shown as `-`.
+    ///
+    /// This is a diagnostic for logs, and is NOT oblivious: the result reveals
+    /// the secret positions of the blocks in the branch.
+    #[cfg(feature = "debug_branch")]
+    pub fn format_branch(&mut self, leaf: u64) -> alloc::string::String {
+        assert!(leaf >> self.height == 1, "not a leaf of this tree");
+        self.release_checkout();
+        self.branch.checkout(&mut self.storage, leaf);
+        let result = self.branch.format();
+        self.branch.checkin(&mut self.storage);
         result
     }
-}
 
-/// Struct which represents a branch which we have checked out, including its
-/// leaf and the associated data.
-///
-/// This struct is a member of PathORAM and is long lived, so that we don't
-/// call malloc with every checkout.
-///
-/// This is mainly just an organizational thing.
-struct BranchCheckout<ValueSize, Z>
-where
-    ValueSize: ArrayLength<u8> + PartialDiv<U8> + PartialDiv<U64>,
-    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
-    Prod<Z, ValueSize>: ArrayLength<u8> + PartialDiv<U8>,
-    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
-{
-    /// The leaf of branch that is currently checked-out. 0 if no existing checkout.
-    leaf: u64,
-    /// The scratch-space for checked-out branch data
-    data: Vec<A64Bytes<Prod<Z, ValueSize>>>,
-    /// The scratch-space for checked-out branch metadata
-    meta: Vec<A8Bytes<Prod<Z, MetaSize>>>,
-    /// Phantom data for ValueSize
-    _value_size: PhantomData<fn() -> ValueSize>,
-}
+    /// Evict from the stash into every branch of the tree, once each.
+    ///
+    /// This is maintenance which drains the stash as far as possible in one
+    /// pass, e.g. after unpinning keys which accumulated in the stash while they
+    /// were pinned. Every unpinned stash item is offered its own branch, so it
+    /// is placed unless that branch is full. Pinned items stay in the stash.
+    /// This is also useful before taking a snapshot of the storage, so that
+    /// as little as possible is left outside of it.
+    ///
+    /// The branches are visited in a fixed order, so this is oblivious, but it
+    /// costs one branch checkout per leaf of the tree.
+    pub fn sweep_stash(&mut self) {
+        self.release_checkout();
+        for leaf in (1u64 << self.height)..(2u64 << self.height) {
+            self.evict_branch(leaf);
+        }
+    }
 
-impl<ValueSize, Z> Default for BranchCheckout<ValueSize, Z>
-where
-    ValueSize: ArrayLength<u8> + PartialDiv<U8> + PartialDiv<U64>,
-    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
-    Prod<Z, ValueSize>: ArrayLength<u8> + PartialDiv<U8>,
-    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
-{
-    fn default() -> Self {
-        Self {
-            leaf: 0,
-            data: Default::default(),
-            meta: Default::default(),
-            _value_size: Default::default(),
+    /// Move all of the live stash items to the front of the stash, keeping their
+    /// order, so that the vacancies are contiguous at the back.
+    ///
+    /// This is maintenance, e.g. after `StashPlacement::Spread` or deletions
+    /// leave the stash fragmented. It is oblivious: every item is offered to
+    /// every slot before it, in a fixed order, as in branch packing.
+    pub fn compact_stash(&mut self) {
+        for idx in 1..self.stash_data.len() {
+            let (lower_data, upper_data) = self.stash_data.split_at_mut(idx);
+            let (lower_meta, upper_meta) = self.stash_meta.split_at_mut(idx);
+            details::ct_insert(
+                1.into(),
+                &upper_data[0],
+                &mut upper_meta[0],
+                lower_data,
+                lower_meta,
+            );
         }
     }
-}
 
-impl<ValueSize, Z> BranchCheckout<ValueSize, Z>
-where
-    ValueSize: ArrayLength<u8> + PartialDiv<U8> + PartialDiv<U64>,
-    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
-    Prod<Z, ValueSize>: ArrayLength<u8> + PartialDiv<U8>,
-    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
-{
-    /// Try to extract an item from the branch
-    pub fn ct_find_and_remove(
+    /// Touch all of the storage, so that later accesses have predictable latency.
+    /// See `ORAMStorage::prewarm`. This is non-secret maintenance.
+    pub fn prewarm(&mut self) {
+        self.release_checkout();
+        self.storage.prewarm();
+    }
+
+    /// Read the raw metadata of the bucket at an index in the tree, for tools
+    /// which inspect the layout of the tree. See `ORAMStorage::read_bucket_meta`.
+    /// This is non-secret maintenance: the index is revealed, and the result
+    /// reveals where blocks are stored.
+    pub fn read_bucket_meta(&mut self, index: u64) -> A8Bytes<Prod<Z, MetaSize>> {
+        self.release_checkout();
+        assert!(
+            index != 0 && index < (2u64 << self.height),
+            "bucket index out of bounds"
+        );
+        self.storage.read_bucket_meta(index)
+    }
+
+    /// Scramble keys with a keyed pseudorandom permutation, before they index
+    /// the position map or are stored as block nums. Then the layout of the
+    /// position map, and the block nums in the tree, are not correlated with
+    /// the keys the caller uses. Everything which reports a key, such as the
+    /// predicate of `delete_where`, sees the original key.
+    ///
+    /// The key of the permutation is drawn from our rng. This panics if called
+    /// after the first access, since blocks written before it would become
+    /// unreachable. The permutation is constant time, so it leaks nothing.
+    pub fn enable_key_scrambling(&mut self) {
+        assert!(
+            !self.accessed,
+            "key scrambling must be enabled before the first access"
+        );
+        let len = self.pos.len();
+        assert!(len & (len - 1) == 0, "size must be a power of two");
+        let bits = log2_ceil(len);
+        self.key_scrambler = Some(KeyScrambler::new(bits, &mut self.rng));
+    }
+
+    /// Apply the key scrambling permutation, if enabled
+    fn scramble_key(&self, key: u64) -> u64 {
+        match self.key_scrambler.as_ref() {
+            Some(key_scrambler) => key_scrambler.permute(key),
+            None => key,
+        }
+    }
+
+    /// Start maintaining a running checksum of the storage, which can be used
+    /// to detect out-of-band tampering with it. See `verify_checksum`.
+    ///
+    /// This requires a sweep over the whole tree to compute the initial value.
+    /// Afterwards, each bucket which is checked out or checked in is hashed,
+    /// which depends only on the (public) leaf of the branch.
+    pub fn enable_checksum(&mut self) {
+        self.release_checkout();
+        self.branch.checksum = Some(StorageChecksum::new(&mut self.rng));
+        let value = self.compute_checksum();
+        if let Some(checksum) = self.branch.checksum.as_mut() {
+            checksum.set_value(value);
+        }
+    }
+
+    /// Read back every branch after it is checked in, and check that the
+    /// storage retained what was written, to catch storage faults.
+    ///
+    /// A fault is reported as `ORAMError::WriteVerificationFailed` by the
+    /// access during which it occurred, or by the next access, if it occurred
+    /// during maintenance. This doubles the storage traffic of each checkin.
+    /// Whether a fault occurred is revealed, but nothing about the contents.
+    pub fn enable_write_verification(&mut self) {
+        self.branch.verify_writes = true;
+    }
+
+    /// Check that the storage matches the running checksum, returning false if
+    /// it was modified other than by this ORAM since `enable_checksum`.
+    ///
+    /// This requires a sweep over the whole tree, in a fixed order.
+    /// Panics if `enable_checksum` was not called.
+    pub fn verify_checksum(&mut self) -> bool {
+        assert!(self.branch.checksum.is_some(), "checksum is not enabled");
+        let value = self.compute_checksum();
+        self.branch.checksum.as_ref().map(StorageChecksum::value) == Some(value)
+    }
+
+    /// Compute the checksum of the whole storage from scratch, using the keys
+    /// of the running checksum, by visiting every branch.
+    /// Checking out and checking in each branch leaves the running value as is.
+    fn compute_checksum(&mut self) -> u64 {
+        self.release_checkout();
+        let mut result = 0u64;
+        for leaf in (1u64 << self.height)..(2u64 << self.height) {
+            self.branch.checkout(&mut self.storage, leaf);
+            if let Some(checksum) = self.branch.checksum.as_ref() {
+                for idx in 0..self.branch.data.len() {
+                    // Count each bucket only once, from the first leaf beneath it
+                    if leaf & ((1u64 << idx) - 1) == 0 {
+                        result ^= checksum.bucket_hash(
+                            leaf >> idx,
+                            &self.branch.data[idx],
+                            &self.branch.meta[idx],
+                        );
+                    }
+                }
+            }
+            self.branch.checkin(&mut self.storage);
+        }
+        result
+    }
+
+    /// Compute a digest of the logical contents of the ORAM, e.g. to check
+    /// that a restored ORAM matches the one which was saved.
+    ///
+    /// This is the sum, over every live block in the tree, the stash and the
+    /// spill area, of a 256-bit hash of the block number and value, taken
+    /// separately in each 64-bit word. So it doesn't depend on where blocks
+    /// are, and ORAMs with the same blocks have the same digest, regardless of
+    /// their seeds or access history. Unlike an XOR, a sum doesn't let a
+    /// duplicated block cancel itself out.
+    /// The hash is not keyed, so this detects accidents, not tampering; see
+    /// `enable_checksum` for that.
+    ///
+    /// This requires a sweep over the whole tree, visiting each bucket once,
+    /// and every slot is hashed whether or not it is occupied. It takes
+    /// `&mut self` because the tree is read through the storage, and any
+    /// branch held over by checkout reuse is checked in first.
+    pub fn digest(&mut self) -> [u8; 32] {
+        self.release_checkout();
+        let key_scrambler = self.key_scrambler.clone();
+        let unscramble = |block_num: u64| unscramble(&key_scrambler, block_num);
+        let mut acc = [0u64; 4];
+        self.storage.for_each_bucket(&mut |_, data, meta| {
+            let bucket_data: &[A64Bytes<ValueSize>] = data.as_aligned_chunks();
+            let bucket_meta: &[A8Bytes<MetaSize>] = meta.as_aligned_chunks();
+            details::ct_digest(&mut acc, &unscramble, bucket_data, bucket_meta);
+        });
+        details::ct_digest(&mut acc, &unscramble, &self.stash_data, &self.stash_meta);
+        details::ct_digest(&mut acc, &unscramble, &self.spill_data, &self.spill_meta);
+
+        let mut result = [0u8; 32];
+        for (chunk, word) in result.chunks_mut(8).zip(acc.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        result
+    }
+
+    /// Compute the smallest stash which would have held the blocks of a
+    /// recorded trace of accesses, to an ORAM of a given size, without
+    /// overflowing. This is an offline analysis, e.g. for tuning the stash
+    /// size from real traces.
+    ///
+    /// The trace is replayed with the default configuration, and a stash large
+    /// enough for every block. Until the stash would overflow, a smaller
+    /// stash makes the same eviction decisions, so the answer is the most
+    /// blocks which were in the stash at once, which is just before eviction.
+    ///
+    /// Panics if the trace is inconsistent, i.e. a key is checked out at a leaf
+    /// other than the one it was assigned by its previous access.
+    pub fn min_stash_for_trace<
+        SC: ORAMStorageCreator<Prod<Z, ValueSize>, Prod<Z, MetaSize>, Output = StorageType>,
+        F: FnMut() -> RngType + 'static,
+    >(
+        size: u64,
+        trace: &[TraceAccess],
+        rng_maker: &mut F,
+    ) -> usize {
+        let max_blocks = core::cmp::min(size, trace.len() as u64) as usize;
+        let pos = ReplayPositionMap::new(size, trace);
+        let mut oram = Self::new_with_position_map::<SC, F>(Box::new(pos), max_blocks, rng_maker);
+        oram.set_leaf_assigner(Box::new(ReplayLeafAssigner::new(trace)));
+
+        let mut occupancy = 0usize;
+        let mut result = 0usize;
+        for access in trace {
+            // The accessed block goes into the stash, unless it is there already
+            let in_stash = oram.stash_meta.iter().any(|meta| {
+                !bool::from(meta_is_vacant(meta)) && meta_block_num(meta) == access.key
+            });
+            result = result.max(occupancy + !in_stash as usize);
+            oram.access_impl(access.key, None, 1, true, |_, _| ())
+                .expect("replay failed");
+            occupancy = details::ct_count_occupied(&oram.stash_meta) as usize;
+        }
+        result
+    }
+
+    /// Replace the strategy used to choose new leaves for accessed blocks.
+    ///
+    /// The default is `UniformLeafAssigner`, which is what Path ORAM specifies.
+    /// See the `leaf_assigner` module for caveats about other strategies.
+    pub fn set_leaf_assigner(
+        &mut self,
+        leaf_assigner: Box<dyn LeafAssigner<RngType> + Send + Sync + 'static>,
+    ) {
+        self.leaf_assigner = leaf_assigner;
+    }
+
+    /// Set where accessed blocks are placed in the stash. The default is
+    /// `StashPlacement::FrontFill`.
+    pub fn set_stash_placement(&mut self, stash_placement: StashPlacement) {
+        self.stash_placement = stash_placement;
+    }
+
+    /// Get the stash slot where the search for a vacancy starts, for the
+    /// placement strategy. This depends only on the number of accesses.
+    fn next_stash_start(&mut self) -> usize {
+        match self.stash_placement {
+            StashPlacement::FrontFill => 0,
+            StashPlacement::Spread => {
+                let counter = self.stash_placement_counter;
+                self.stash_placement_counter = counter.wrapping_add(1);
+                (counter % self.stash_meta.len().max(1) as u64) as usize
+            }
+        }
+    }
+
+    /// Set the procedure used to evict from the stash into a branch. The
+    /// default is `ItemFirstEvictionStrategy`.
+    pub fn set_eviction_strategy(
+        &mut self,
+        eviction_strategy: Box<dyn EvictionStrategy<ValueSize, Z>>,
+    ) {
+        self.eviction_strategy = eviction_strategy;
+    }
+
+    /// Enable or disable eviction of a sibling branch after each access.
+    ///
+    /// With `Some(level)`, after the accessed branch is evicted, we also evict
+    /// the branch obtained by flipping the accessed branch at the given level
+    /// of the tree. Level 1 is the children of the root, and level `height`
+    /// is the leaves, so higher levels give "closer" siblings.
+    /// This spreads load across the subtree, at the cost of one more branch
+    /// checkout per access.
+    ///
+    /// The sibling is computed structurally from the accessed leaf, which is
+    /// revealed by the access anyways, so this leaks nothing further.
+    pub fn set_sibling_eviction_level(&mut self, level: Option<u32>) {
+        if let Some(level) = level {
+            assert!(
+                level >= 1 && level <= self.height,
+                "sibling eviction level must be in the range 1..=height"
+            );
+            assert!(
+                !self.reuse_checkout,
+                "sibling eviction cannot be used with checkout reuse"
+            );
+        }
+        self.sibling_eviction_level = level;
+    }
+
+    /// Set the number of full stash scans (eviction rounds) done per access.
+    ///
+    /// The first round evicts into the accessed branch. Each additional round
+    /// evicts into another branch, taken in reverse-lexicographic order of the
+    /// leaves, as in Gentry et al. More rounds drain the stash faster, at the
+    /// cost of one more branch checkout and stash scan each.
+    /// Scanning the same branch again would not help, because each scan
+    /// already places every item that fits.
+    ///
+    /// The extra branches don't depend on any secret, so this leaks nothing.
+    /// The default is 1.
+    pub fn set_eviction_rounds(&mut self, rounds: u32) {
+        assert!(rounds >= 1, "there must be at least one eviction round");
+        assert!(
+            rounds == 1 || !self.reuse_checkout,
+            "extra eviction rounds cannot be used with checkout reuse"
+        );
+        self.eviction_rounds = rounds;
+    }
+
+    /// Keep the accessed branch checked out after an access, and only check it
+    /// in when an access targets a different leaf, or when any other operation
+    /// needs the storage. This saves storage I/O when consecutive accesses are
+    /// to the same leaf. It cannot be combined with sibling eviction or extra
+    /// eviction rounds, since those evict into other branches. An access with
+    /// extra rounds from `try_access_with_eviction_rounds` checks the branch in.
+    ///
+    /// The accessed leaf is revealed by every access anyways, so skipping the
+    /// checkout leaks nothing new. While a branch is held, the storage is out of
+    /// date. Every operation which uses the storage checks the branch in first,
+    /// and so does dropping the ORAM, so a persistent storage is left up to date.
+    /// The default is false. Disabling this releases the held branch.
+    pub fn set_checkout_reuse(&mut self, enabled: bool) {
+        if enabled {
+            assert!(
+                self.sibling_eviction_level.is_none() && self.eviction_rounds == 1,
+                "checkout reuse cannot be used with sibling eviction or extra eviction rounds"
+            );
+        }
+        self.reuse_checkout = enabled;
+        if !enabled {
+            self.release_checkout();
+        }
+    }
+
+    /// Check in the branch held by `set_checkout_reuse`, if any.
+    pub fn release_checkout(&mut self) {
+        if self.branch.leaf != 0 {
+            self.branch.checkin(&mut self.storage);
+        }
+        debug_assert!(self.branch.leaf == 0);
+    }
+
+    /// Set how the branches for extra eviction rounds are chosen.
+    /// The default is `BranchSelector::ReverseLex`.
+    pub fn set_branch_selector(&mut self, branch_selector: BranchSelector) {
+        self.branch_selector = branch_selector;
+    }
+
+    /// Get the position in the reverse-lexicographic schedule of extra eviction
+    /// rounds, i.e. the number of extra branches evicted so far.
+    /// This can be saved, and restored with `set_schedule_position`, so that the
+    /// schedule resumes where it left off, e.g. after restoring from a backup.
+    /// `BranchSelector::DepthFirst` advances the same position.
+    pub fn schedule_position(&self) -> u64 {
+        self.reverse_lex_counter
+    }
+
+    /// Set the position in the reverse-lexicographic schedule of extra eviction
+    /// rounds. See `schedule_position`. This is public, so it leaks nothing.
+    ///
+    /// With `BranchSelector::StalestSubtree`, the choice of subtree also depends
+    /// on the eviction counts of the subtrees, which are not restored by this.
+    pub fn set_schedule_position(&mut self, position: u64) {
+        self.reverse_lex_counter = position;
+    }
+
+    /// Get the leaf for the next extra eviction round, using the branch selector
+    fn next_extra_leaf(&mut self) -> u64 {
+        match self.branch_selector {
+            BranchSelector::ReverseLex => self.next_reverse_lex_leaf(),
+            BranchSelector::DepthFirst => {
+                let counter = self.reverse_lex_counter;
+                self.reverse_lex_counter = self.reverse_lex_counter.wrapping_add(1);
+                depth_first_leaf(counter, self.height)
+            }
+            BranchSelector::StalestSubtree => {
+                let level = self.height.min(SELECTOR_SUBTREE_LEVEL);
+                let (subtree, _) = self
+                    .subtree_evictions
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, count)| **count)
+                    .expect("there is always at least one subtree");
+                let sub_height = self.height - level;
+                // The high bits of a reverse-lexicographic leaf are themselves in
+                // reverse-lexicographic order, for the smaller height
+                let sub_leaf = (self.next_reverse_lex_leaf() >> level) & ((1u64 << sub_height) - 1);
+                (1u64 << self.height) | ((subtree as u64) << sub_height) | sub_leaf
+            }
+        }
+    }
+
+    /// Get the next leaf in reverse-lexicographic order, and advance the counter.
+    /// Reversing the bits of a counter spreads consecutive evictions across
+    /// the tree, so that every bucket is evicted into regularly.
+    fn next_reverse_lex_leaf(&mut self) -> u64 {
+        let counter = self.reverse_lex_counter;
+        self.reverse_lex_counter = self.reverse_lex_counter.wrapping_add(1);
+        reverse_lex_leaf(counter, self.height)
+    }
+
+    /// Evict from the stash into the currently checked-out branch.
+    /// This packs the branch first, then tries to insert every stash item
+    /// which is not pinned.
+    fn evict_into_branch(&mut self) {
+        debug_assert!(self.branch.leaf != 0);
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.evictions += 1;
+        }
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!("oram_evict", strategy = self.eviction_strategy.name());
+        #[cfg(feature = "tracing")]
+        let _guard = span.enter();
+        #[cfg(debug_assertions)]
+        let live_before = self.count_live_blocks();
+        #[cfg(feature = "eviction_log")]
+        let stash_before = details::ct_count_occupied(&self.stash_meta);
+        let level = self.height.min(SELECTOR_SUBTREE_LEVEL);
+        let subtree = (self.branch.leaf >> (self.height - level)) & ((1u64 << level) - 1);
+        self.subtree_evictions[subtree as usize] += 1;
+        self.branch.pack();
+        self.eviction_strategy
+            .evict(&mut self.branch, &mut self.stash_data, &mut self.stash_meta);
+        #[cfg(debug_assertions)]
+        assert_eq!(
+            live_before,
+            self.count_live_blocks(),
+            "eviction must not duplicate or lose blocks"
+        );
+        #[cfg(feature = "eviction_log")]
+        if let Some(sink) = self.eviction_sink.as_mut() {
+            sink(&EvictionRecord {
+                leaf: self.branch.leaf,
+                stash_before,
+                stash_after: details::ct_count_occupied(&self.stash_meta),
+                level_loads: self
+                    .branch
+                    .buckets_root_to_leaf()
+                    .map(|(_, _, meta)| details::ct_count_occupied(meta.as_aligned_chunks()))
+                    .collect(),
+            });
+        }
+    }
+
+    /// Count the live blocks in the stash and the checked-out branch.
+    /// This is only used to check that eviction conserves blocks.
+    #[cfg(any(debug_assertions, test))]
+    fn count_live_blocks(&self) -> u64 {
+        let mut result = details::ct_count_occupied(&self.stash_meta);
+        for bucket_meta in self.branch.meta.iter() {
+            result += details::ct_count_occupied(bucket_meta.as_aligned_chunks());
+        }
+        result
+    }
+
+    /// Checkout a branch, evict from the stash into it, and check it back in.
+    fn evict_branch(&mut self, leaf: u64) {
+        debug_assert!(self.branch.leaf == 0);
+        self.branch.checkout(&mut self.storage, leaf);
+        self.evict_into_branch();
+        self.branch.checkin(&mut self.storage);
+        debug_assert!(self.branch.leaf == 0);
+    }
+}
+
+/// Undo the key scrambling permutation, if any, on a block num
+fn unscramble(key_scrambler: &Option<KeyScrambler>, block_num: u64) -> u64 {
+    match key_scrambler.as_ref() {
+        Some(key_scrambler) => key_scrambler.invert(block_num),
+        None => block_num,
+    }
+}
+
+/// Get the leaf at a given position in the reverse-lexicographic order of
+/// the leaves of a tree of a given height. This order repeats with period
+/// 2^height.
+fn reverse_lex_leaf(counter: u64, height: u32) -> u64 {
+    if height == 0 {
+        return 1;
+    }
+    (1u64 << height) | (counter.reverse_bits() >> (64 - height))
+}
+
+/// Get the leaf at a given position in the left-to-right (depth-first) order of
+/// the leaves of a tree of a given height. This order repeats with period
+/// 2^height.
+fn depth_first_leaf(counter: u64, height: u32) -> u64 {
+    (1u64 << height) | (counter & ((1u64 << height) - 1))
+}
+
+/// Get every leaf of a tree of a given height, in the reverse-lexicographic
+/// order used for extra eviction rounds, over one full period.
+/// This is 2^height leaves, so it is only practical for small trees.
+pub fn reverse_lex_schedule(tree_height: u32) -> Vec<u64> {
+    (0..1u64 << tree_height)
+        .map(|counter| reverse_lex_leaf(counter, tree_height))
+        .collect()
+}
+
+/// Get the leaf of the sibling branch at a given level, by flipping the bit of
+/// the leaf which selects the child at that level.
+fn sibling_leaf(leaf: u64, level: u32, height: u32) -> u64 {
+    debug_assert!(level >= 1 && level <= height);
+    leaf ^ (1u64 << (height - level))
+}
+
+/// The number of buckets on a branch of a tree of a given height, counting
+/// both the root and the leaf. This is the length of a checked-out branch.
+pub fn branch_len(tree_height: u32) -> usize {
+    tree_height as usize + 1
+}
+
+/// The number of levels on a branch of a tree of a given height, when the stash
+/// is counted as one more level above the root, as some eviction algorithms do.
+pub fn stash_adjusted_len(tree_height: u32) -> usize {
+    branch_len(tree_height) + 1
+}
+
+/// The leaves of a tree of a given height whose branches pass through a bucket,
+/// as a range of TreeIndex values. These are contiguous, and there are
+/// 2^(height - level) of them, where level is the height of the bucket's index.
+pub fn leaves_under_bucket(bucket_index: u64, tree_height: u32) -> Range<u64> {
+    debug_assert!(bucket_index != 0, "0 is not a valid TreeIndex");
+    let level = bucket_index.height();
+    debug_assert!(level <= tree_height, "bucket is below the leaves");
+    let shift = tree_height - level;
+    (bucket_index << shift)..((bucket_index + 1) << shift)
+}
+
+/// Check in a branch held by checkout reuse, so the storage is up to date
+impl<ValueSize, Z, StorageType, RngType> Drop for PathORAM<ValueSize, Z, StorageType, RngType>
+where
+    ValueSize: ArrayLength<u8> + PartialDiv<U8> + PartialDiv<U64>,
+    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
+    RngType: RngCore + CryptoRng + Send + Sync + 'static,
+    StorageType: ORAMStorage<Prod<Z, ValueSize>, Prod<Z, MetaSize>> + Send + Sync + 'static,
+    Prod<Z, ValueSize>: ArrayLength<u8> + PartialDiv<U8>,
+    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
+{
+    fn drop(&mut self) {
+        self.release_checkout();
+    }
+}
+
+impl<ValueSize, Z, StorageType, RngType> ORAM<ValueSize>
+    for PathORAM<ValueSize, Z, StorageType, RngType>
+where
+    ValueSize: ArrayLength<u8> + PartialDiv<U8> + PartialDiv<U64>,
+    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
+    RngType: RngCore + CryptoRng + Send + Sync + 'static,
+    StorageType: ORAMStorage<Prod<Z, ValueSize>, Prod<Z, MetaSize>> + Send + Sync + 'static,
+    Prod<Z, ValueSize>: ArrayLength<u8> + PartialDiv<U8>,
+    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
+{
+    fn len(&self) -> u64 {
+        self.pos.len()
+    }
+    // TODO: We should try implementing a circuit-ORAM like approach also
+    fn access<T, F: FnOnce(&mut A64Bytes<ValueSize>) -> T>(&mut self, key: u64, f: F) -> T {
+        match self.try_access(key, f) {
+            Ok(result) => result,
+            Err(err) => panic!("PathORAM access failed: {}", err),
+        }
+    }
+    // A read of a key which was never written must not make it exist, or
+    // `upsert` would see it as existing afterwards
+    fn read(&mut self, key: u64) -> A64Bytes<ValueSize> {
+        self.access_ref(key, |val| val.clone())
+    }
+    fn access_ref<T, F: FnOnce(&A64Bytes<ValueSize>) -> T>(&mut self, key: u64, f: F) -> T {
+        match self.access_impl(key, None, self.eviction_rounds, false, |data, _| f(data)) {
+            Ok(result) => result,
+            Err(err) => panic!("PathORAM access failed: {}", err),
+        }
+    }
+}
+
+/// Struct which represents a branch which we have checked out, including its
+/// leaf and the associated data.
+///
+/// This struct is a member of PathORAM and is long lived, so that we don't
+/// call malloc with every checkout.
+///
+/// This is mainly just an organizational thing.
+pub struct BranchCheckout<ValueSize, Z>
+where
+    ValueSize: ArrayLength<u8> + PartialDiv<U8> + PartialDiv<U64>,
+    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
+    Prod<Z, ValueSize>: ArrayLength<u8> + PartialDiv<U8>,
+    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
+{
+    /// The leaf of branch that is currently checked-out. 0 if no existing checkout.
+    leaf: u64,
+    /// The scratch-space for checked-out branch data
+    data: Vec<A64Bytes<Prod<Z, ValueSize>>>,
+    /// The scratch-space for checked-out branch metadata
+    meta: Vec<A8Bytes<Prod<Z, MetaSize>>>,
+    /// The running checksum of the storage, updated on checkout and checkin.
+    /// None if this is disabled.
+    checksum: Option<StorageChecksum>,
+    /// Whether to read back each branch after it is checked in
+    verify_writes: bool,
+    /// Set when a branch did not read back as it was written, until reported
+    write_fault: bool,
+    /// The scratch-space for the expected data of a branch being verified
+    expected_data: Vec<A64Bytes<Prod<Z, ValueSize>>>,
+    /// The scratch-space for the expected metadata of a branch being verified
+    expected_meta: Vec<A8Bytes<Prod<Z, MetaSize>>>,
+    /// Phantom data for ValueSize
+    _value_size: PhantomData<fn() -> ValueSize>,
+}
+
+impl<ValueSize, Z> Default for BranchCheckout<ValueSize, Z>
+where
+    ValueSize: ArrayLength<u8> + PartialDiv<U8> + PartialDiv<U64>,
+    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
+    Prod<Z, ValueSize>: ArrayLength<u8> + PartialDiv<U8>,
+    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
+{
+    fn default() -> Self {
+        Self {
+            leaf: 0,
+            data: Default::default(),
+            meta: Default::default(),
+            checksum: None,
+            verify_writes: false,
+            write_fault: false,
+            expected_data: Default::default(),
+            expected_meta: Default::default(),
+            _value_size: Default::default(),
+        }
+    }
+}
+
+impl<ValueSize, Z> BranchCheckout<ValueSize, Z>
+where
+    ValueSize: ArrayLength<u8> + PartialDiv<U8> + PartialDiv<U64>,
+    Z: Unsigned + Mul<ValueSize> + Mul<MetaSize>,
+    Prod<Z, ValueSize>: ArrayLength<u8> + PartialDiv<U8>,
+    Prod<Z, MetaSize>: ArrayLength<u8> + PartialDiv<U8>,
+{
+    /// Try to extract an item from the branch
+    pub fn ct_find_and_remove(
         &mut self,
         condition: Choice,
         query: &u64,
@@ -329,6 +1610,36 @@ where
         );
     }
 
+    /// Move the item in a slot of one checked-out branch to a slot of another,
+    /// if a condition is true and the destination slot is vacant. The source
+    /// slot is left vacant. Returns whether the item was moved.
+    ///
+    /// A slot is given as (branch index, index within the bucket), where branch
+    /// index 0 is the leaf bucket. The slots are public, and the condition and
+    /// contents are not revealed. The caller is responsible for the destination
+    /// being on the branch of the item's leaf, see `check_invariants`.
+    pub fn ct_transfer(
+        condition: Choice,
+        src: &mut Self,
+        src_slot: (usize, usize),
+        dst: &mut Self,
+        dst_slot: (usize, usize),
+    ) -> Choice {
+        debug_assert!(src.leaf != 0 && dst.leaf != 0);
+        let src_data: &mut [A64Bytes<ValueSize>] = src.data[src_slot.0].as_mut_aligned_chunks();
+        let src_meta: &mut [A8Bytes<MetaSize>] = src.meta[src_slot.0].as_mut_aligned_chunks();
+        let dst_data: &mut [A64Bytes<ValueSize>] = dst.data[dst_slot.0].as_mut_aligned_chunks();
+        let dst_meta: &mut [A8Bytes<MetaSize>] = dst.meta[dst_slot.0].as_mut_aligned_chunks();
+        let (src_data, src_meta) = (&mut src_data[src_slot.1], &mut src_meta[src_slot.1]);
+        let (dst_data, dst_meta) = (&mut dst_data[dst_slot.1], &mut dst_meta[dst_slot.1]);
+
+        let condition = condition & !meta_is_vacant(src_meta) & meta_is_vacant(dst_meta);
+        dst_data.cmov(condition, src_data);
+        dst_meta.cmov(condition, src_meta);
+        meta_set_vacant(condition, src_meta);
+        condition
+    }
+
     /// This is the Path ORAM branch packing procedure, which we implement
     /// obliviously in a naive way.
     pub fn pack(&mut self) {
@@ -364,6 +1675,145 @@ where
         debug_assert!(self.leaf != 0);
     }
 
+    /// Iterate over the buckets of the checked-out branch, from the root bucket
+    /// down to the leaf bucket, as (level, data, metadata). The level of a
+    /// bucket is its height in the tree, so the root is at level 0, and the
+    /// leaf bucket is at the height of the tree.
+    ///
+    /// This is the order in which `PathORAM::format_branch` lists the buckets.
+    /// Note that it is the reverse of the order of the `data` and `meta`
+    /// buffers, where the leaf bucket is at index 0.
+    pub fn buckets_root_to_leaf(
+        &self,
+    ) -> impl Iterator<
+        Item = (
+            u32,
+            &A64Bytes<Prod<Z, ValueSize>>,
+            &A8Bytes<Prod<Z, MetaSize>>,
+        ),
+    > + '_ {
+        self.buckets_leaf_to_root().rev()
+    }
+
+    /// Iterate over the buckets of the checked-out branch, from the leaf bucket
+    /// up to the root bucket, as (level, data, metadata). See
+    /// `buckets_root_to_leaf`.
+    pub fn buckets_leaf_to_root(
+        &self,
+    ) -> impl DoubleEndedIterator<
+        Item = (
+            u32,
+            &A64Bytes<Prod<Z, ValueSize>>,
+            &A8Bytes<Prod<Z, MetaSize>>,
+        ),
+    > + '_ {
+        debug_assert!(self.leaf != 0);
+        let height = self.leaf.height();
+        self.data
+            .iter()
+            .zip(self.meta.iter())
+            .enumerate()
+            .map(move |(idx, (data, meta))| (height - idx as u32, data, meta))
+    }
+
+    /// Render the checked-out branch as a tree, see `PathORAM::format_branch`
+    #[cfg(feature = "debug_branch")]
+    fn format(&self) -> alloc::string::String {
+        use core::fmt::Write;
+        debug_assert!(self.leaf != 0);
+        let mut result = alloc::string::String::new();
+        writeln!(&mut result, "branch to leaf {}", self.leaf).unwrap();
+        let height = self.leaf.height();
+        for (level, _, bucket_meta) in self.buckets_root_to_leaf() {
+            write!(
+                &mut result,
+                "{:indent$}bucket {}:",
+                "",
+                self.leaf >> (height - level),
+                indent = 2 * level as usize
+            )
+            .unwrap();
+            let bucket_meta: &[A8Bytes<MetaSize>] = bucket_meta.as_aligned_chunks();
+            for meta in bucket_meta {
+                if bool::from(meta_is_vacant(meta)) {
+                    write!(&mut result, " [-]").unwrap();
+                } else {
+                    let pin_mark = if bool::from(meta_is_pinned(meta)) {
+                        "*"
+                    } else {
+                        ""
+                    };
+                    write!(
+                        &mut result,
+                        " [{}{} -> {}]",
+                        meta_block_num(meta),
+                        pin_mark,
+                        meta_leaf_num(meta)
+                    )
+                    .unwrap();
+                }
+            }
+            writeln!(&mut result).unwrap();
+        }
+        result
+    }
+
+    /// Check the invariants of the checked-out branch, together with the stash,
+    /// panicking if any fails:
+    /// - Every block has a leaf at the height of the tree
+    /// - Every block in the branch is in a bucket on the branch of its own leaf
+    /// - No block num appears more than once
+    ///
+    /// This is not constant time, and is meant for tests and for tools which
+    /// inspect the tree, not for the access path.
+    pub fn check_invariants(&self, stash_meta: &[A8Bytes<MetaSize>]) {
+        debug_assert!(self.leaf != 0);
+        let height = self.leaf.height();
+        let mut block_nums = Vec::new();
+        for meta in stash_meta {
+            if !bool::from(meta_is_vacant(meta)) {
+                assert_eq!(
+                    meta_leaf_num(meta).height(),
+                    height,
+                    "block {} has an invalid leaf",
+                    meta_block_num(meta)
+                );
+                block_nums.push(meta_block_num(meta));
+            }
+        }
+        for (idx, bucket) in self.meta.iter().enumerate() {
+            let bucket_meta: &[A8Bytes<MetaSize>] = bucket.as_aligned_chunks();
+            for meta in bucket_meta {
+                if !bool::from(meta_is_vacant(meta)) {
+                    let leaf = *meta_leaf_num(meta);
+                    assert_eq!(
+                        leaf.height(),
+                        height,
+                        "block {} has an invalid leaf",
+                        meta_block_num(meta)
+                    );
+                    assert_eq!(
+                        leaf >> idx,
+                        self.leaf >> idx,
+                        "block {} in bucket {} is not on the branch of its leaf {}",
+                        meta_block_num(meta),
+                        self.leaf >> idx,
+                        leaf
+                    );
+                    block_nums.push(meta_block_num(meta));
+                }
+            }
+        }
+        block_nums.sort_unstable();
+        for pair in block_nums.windows(2) {
+            assert!(
+                pair[0] != pair[1],
+                "block {} appears more than once",
+                pair[0]
+            );
+        }
+    }
+
     /// Checkout a branch from storage into ourself
     pub fn checkout(
         &mut self,
@@ -371,11 +1821,18 @@ where
         leaf: u64,
     ) {
         debug_assert!(self.leaf == 0);
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!("oram_checkout", buckets = branch_len(leaf.height()));
+        #[cfg(feature = "tracing")]
+        let _guard = span.enter();
         self.data
-            .resize_with(leaf.height() as usize + 1, Default::default);
+            .resize_with(branch_len(leaf.height()), Default::default);
         self.meta
-            .resize_with(leaf.height() as usize + 1, Default::default);
+            .resize_with(branch_len(leaf.height()), Default::default);
         storage.checkout(leaf, &mut self.data, &mut self.meta);
+        if let Some(checksum) = self.checksum.as_mut() {
+            checksum.toggle_branch(leaf, &self.data, &self.meta);
+        }
         self.leaf = leaf;
     }
 
@@ -385,10 +1842,45 @@ where
         storage: &mut impl ORAMStorage<Prod<Z, ValueSize>, Prod<Z, MetaSize>>,
     ) {
         debug_assert!(self.leaf != 0);
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!("oram_checkin", buckets = self.data.len());
+        #[cfg(feature = "tracing")]
+        let _guard = span.enter();
+        if let Some(checksum) = self.checksum.as_mut() {
+            checksum.toggle_branch(self.leaf, &self.data, &self.meta);
+        }
+        if self.verify_writes {
+            self.expected_data.clone_from(&self.data);
+            self.expected_meta.clone_from(&self.meta);
+        }
         storage.checkin(self.leaf, &mut self.data, &mut self.meta);
+        if self.verify_writes {
+            self.verify_checkin(storage);
+        }
         self.leaf = 0;
     }
 
+    /// Read back the branch which was just checked in, and compare it with what
+    /// was written, recording a fault if they differ. The branch is then
+    /// checked in again, as it was read.
+    fn verify_checkin(
+        &mut self,
+        storage: &mut impl ORAMStorage<Prod<Z, ValueSize>, Prod<Z, MetaSize>>,
+    ) {
+        storage.checkout(self.leaf, &mut self.data, &mut self.meta);
+        let mut matches = Choice::from(1);
+        for (data, expected) in self.data.iter().zip(self.expected_data.iter()) {
+            matches &= data.as_slice().ct_eq(expected.as_slice());
+        }
+        for (meta, expected) in self.meta.iter().zip(self.expected_meta.iter()) {
+            matches &= meta.as_slice().ct_eq(expected.as_slice());
+        }
+        storage.checkin(self.leaf, &mut self.data, &mut self.meta);
+        if !bool::from(matches) {
+            self.write_fault = true;
+        }
+    }
+
     /// Given a tree-index value (a node in the tree)
     /// Compute the lowest legal index of a bucket in this branch into which it can
     /// be placed. This depends on the common ancestor height of tree_index and self.leaf.
@@ -442,7 +1934,7 @@ where
 }
 
 /// Constant time helper functions
-mod details {
+pub(crate) mod details {
     use super::*;
 
     /// ct_find_and_remove tries to find and remove an item with a particular block num from a mutable sequence,
@@ -471,7 +1963,7 @@ mod details {
             // XXX: Must be constant time and not optimized, may need a better barrier here
             // Maybe just use subtle::Choice
             let test = condition
-                & (query.ct_eq(meta_block_num(&src_meta[idx])))
+                & (query.ct_eq(&meta_block_num(&src_meta[idx])))
                 & !meta_is_vacant(&src_meta[idx]);
             dest_meta.cmov(test, &src_meta[idx]);
             dest_data.cmov(test, &src_data[idx]);
@@ -481,6 +1973,132 @@ mod details {
         }
     }
 
+    /// ct_take_for_bucket tries to find and remove an item from the stash which
+    /// can legally be placed in a particular bucket of a branch, returning it.
+    ///
+    /// The bucket is identified by the leaf of the branch and its index in the
+    /// branch, where index 0 is the leaf bucket. An item can be placed there if
+    /// the common ancestor of its leaf and the branch leaf is at least as deep as
+    /// the bucket. Pinned items are never taken.
+    ///
+    /// Semantics: If condition is true, scan across the stash and find the first
+    ///            non-vacant, non-pinned item which can go in the bucket,
+    ///            then cmov it to the result, and set it vacant in the stash.
+    ///            The returned Choice indicates if an item was found.
+    ///            If nothing was found, the returned data and meta are default (vacant).
+    ///
+    /// Every stash slot is visited and cmov'ed regardless of condition or which
+    /// item (if any) matches, so the whole operation is constant time.
+    pub fn ct_take_for_bucket<ValueSize: ArrayLength<u8>>(
+        condition: Choice,
+        bucket_num: usize,
+        leaf: u64,
+        src_data: &mut [A64Bytes<ValueSize>],
+        src_meta: &mut [A8Bytes<MetaSize>],
+    ) -> (Choice, A64Bytes<ValueSize>, A8Bytes<MetaSize>) {
+        debug_assert!(src_data.len() == src_meta.len());
+        debug_assert!(bucket_num as u32 <= leaf.height());
+        let bucket_height = leaf.height() - bucket_num as u32;
+        let mut found = Choice::from(0);
+        let mut dest_data = A64Bytes::<ValueSize>::default();
+        let mut dest_meta = A8Bytes::<MetaSize>::default();
+        for idx in 0..src_meta.len() {
+            // Vacant items have leaf 0, which is replaced with root to get a
+            // well-defined common ancestor. Their test will be false anyways.
+            let mut item_leaf = *meta_leaf_num(&src_meta[idx]);
+            item_leaf.cmov(item_leaf.ct_eq(&0), &1);
+            let fits = !leaf
+                .common_ancestor_height(&item_leaf)
+                .ct_lt(&bucket_height);
+            let test = condition
+                & !found
+                & fits
+                & !meta_is_vacant(&src_meta[idx])
+                & !meta_is_pinned(&src_meta[idx]);
+            // The destination is vacant until something is found, so this
+            // moves the item out, and leaves a vacant, zeroed slot behind.
+            ct_swap_slots(
+                test,
+                &mut dest_data,
+                &mut dest_meta,
+                &mut src_data[idx],
+                &mut src_meta[idx],
+            );
+            found |= test;
+        }
+        (found, dest_data, dest_meta)
+    }
+
+    /// ct_swap_slots exchanges two slots, e.g. a stash slot and a bucket slot,
+    /// data and metadata together, if condition is true.
+    ///
+    /// Swapping with a vacant slot moves an item in one step, which can be
+    /// simpler than a take followed by an insert.
+    /// Both slots are always read and written, so this is constant time.
+    pub fn ct_swap_slots<ValueSize: ArrayLength<u8>>(
+        condition: Choice,
+        lhs_data: &mut A64Bytes<ValueSize>,
+        lhs_meta: &mut A8Bytes<MetaSize>,
+        rhs_data: &mut A64Bytes<ValueSize>,
+        rhs_meta: &mut A8Bytes<MetaSize>,
+    ) {
+        cswap(condition, lhs_data, rhs_data);
+        cswap(condition, lhs_meta, rhs_meta);
+    }
+
+    /// ct_delete_where marks every non-vacant item in a sequence vacant, if a
+    /// predicate holds for its block num and data, and zeroes its data.
+    ///
+    /// The predicate is evaluated for every item, including vacant ones,
+    /// and the result is only used via Choice, so this is constant time
+    /// as long as the predicate is.
+    pub fn ct_delete_where<ValueSize: ArrayLength<u8>>(
+        pred: &impl Fn(u64, &A64Bytes<ValueSize>) -> bool,
+        src_data: &mut [A64Bytes<ValueSize>],
+        src_meta: &mut [A8Bytes<MetaSize>],
+    ) {
+        debug_assert!(src_data.len() == src_meta.len());
+        for idx in 0..src_meta.len() {
+            let test = Choice::from(pred(meta_block_num(&src_meta[idx]), &src_data[idx]) as u8)
+                & !meta_is_vacant(&src_meta[idx]);
+            meta_set_vacant(test, &mut src_meta[idx]);
+            src_data[idx].ct_clear(test);
+        }
+    }
+
+    /// ct_digest adds the digest of each non-vacant item of a sequence to an
+    /// accumulator, word by word with wrapping, in constant time.
+    /// The block num of each item is mapped by a function first, which must be
+    /// constant-time.
+    pub fn ct_digest<ValueSize: ArrayLength<u8>>(
+        acc: &mut [u64; 4],
+        block_num_map: &impl Fn(u64) -> u64,
+        src_data: &[A64Bytes<ValueSize>],
+        src_meta: &[A8Bytes<MetaSize>],
+    ) {
+        debug_assert!(src_data.len() == src_meta.len());
+        for idx in 0..src_data.len() {
+            let vacant = meta_is_vacant(&src_meta[idx]);
+            let block_num = block_num_map(meta_block_num(&src_meta[idx]));
+            let hash = checksum::block_digest(block_num, &src_data[idx]);
+            for (acc_word, hash_word) in acc.iter_mut().zip(hash.iter()) {
+                let mut word = *hash_word;
+                word.cmov(vacant, &0);
+                *acc_word = acc_word.wrapping_add(word);
+            }
+        }
+    }
+
+    /// ct_count_occupied counts the number of non-vacant items in a sequence,
+    /// in constant time.
+    pub fn ct_count_occupied(src_meta: &[A8Bytes<MetaSize>]) -> u64 {
+        let mut result = 0u64;
+        for meta in src_meta {
+            result += (!meta_is_vacant(meta)).unwrap_u8() as u64;
+        }
+        result
+    }
+
     /// ct_insert tries to insert an item into a mutable sequence
     ///
     /// It takes the source data and source metadata, (the item being inserted),
@@ -513,4 +2131,2155 @@ mod details {
             condition &= !test;
         }
     }
+
+    /// ct_insert_from is like ct_insert, but the scan for a vacant slot starts
+    /// at a (public) starting index, and wraps around to the front.
+    pub fn ct_insert_from<ValueSize: ArrayLength<u8>>(
+        start: usize,
+        mut condition: Choice,
+        src_data: &A64Bytes<ValueSize>,
+        src_meta: &mut A8Bytes<MetaSize>,
+        dest_data: &mut [A64Bytes<ValueSize>],
+        dest_meta: &mut [A8Bytes<MetaSize>],
+    ) {
+        debug_assert!(dest_data.len() == dest_meta.len());
+        debug_assert!(start == 0 || start < dest_meta.len());
+        condition &= !meta_is_vacant(src_meta);
+        for idx in (start..dest_meta.len()).chain(0..start) {
+            // XXX: Must be constant time and not optimized, may need a better barrier here
+            // Maybe just use subtle::Choice
+            let test = condition & meta_is_vacant(&dest_meta[idx]);
+            dest_meta[idx].cmov(test, src_meta);
+            dest_data[idx].cmov(test, src_data);
+            meta_set_vacant(test, src_meta);
+            condition &= !test;
+        }
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use crate::{
+        testing::{a64_bytes, fill_and_exercise, new_test_oram, TestORAM},
+        ParityORAMStorage, ParityORAMStorageCreator, PathORAM4096Z4Creator, U32PositionMapCreator,
+        EVICTION_STRATEGY_NAMES,
+    };
+    use aligned_cmov::typenum::{U1024, U4, U4096};
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use mc_oblivious_traits::{rng_maker, HeapORAMStorage, HeapORAMStorageCreator, ORAMCreator};
+    use test_helper::{run_with_one_seed, run_with_several_seeds, RngType};
+
+    // Count the number of occupied slots at each level of the tree, indexed by height.
+    fn occupancy_by_level(oram: &mut TestORAM) -> Vec<u64> {
+        let height = oram.height;
+        let mut result = vec![0u64; branch_len(height)];
+        for leaf in (1u64 << height)..(2u64 << height) {
+            oram.branch.checkout(&mut oram.storage, leaf);
+            for (idx, bucket) in oram.branch.meta.iter().enumerate() {
+                // Only count each bucket once, from its left-most leaf
+                if leaf & ((1u64 << idx) - 1) == 0 {
+                    let bucket_meta: &[A8Bytes<MetaSize>] = bucket.as_aligned_chunks();
+                    result[height as usize - idx] += bucket_meta
+                        .iter()
+                        .filter(|meta| !bool::from(meta_is_vacant(meta)))
+                        .count() as u64;
+                }
+            }
+            oram.branch.checkin(&mut oram.storage);
+        }
+        result
+    }
+
+    // Measure the fraction of slots occupied at each level of the tree, root
+    // first, averaged over several snapshots taken between rounds of random
+    // reads.
+    fn fill_by_level(oram: &mut TestORAM, rng: &mut RngType) -> Vec<f64> {
+        const SNAPSHOTS: u64 = 5;
+        let mut totals = vec![0u64; branch_len(oram.height)];
+        for _ in 0..SNAPSHOTS {
+            for _ in 0..200 {
+                let idx = rng.next_u64() % oram.len();
+                oram.read(idx);
+            }
+            for (total, count) in totals.iter_mut().zip(occupancy_by_level(oram)) {
+                *total += count;
+            }
+        }
+        totals
+            .iter()
+            .enumerate()
+            .map(|(level, total)| {
+                let capacity = (SNAPSHOTS * U4::U64) << level;
+                *total as f64 / capacity as f64
+            })
+            .collect()
+    }
+
+    // Test that sibling eviction keeps data correct, and balances the load
+    // across the levels of the tree. The upper levels have few buckets, shared
+    // by many branches, and without sibling eviction they fill up more than
+    // the levels below them. With it, the fullest of the upper levels should
+    // be less full.
+    #[test]
+    fn sibling_eviction_balances_level_load() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut plain = new_test_oram(&mut maker);
+            let mut sibling = new_test_oram(&mut maker);
+            sibling.set_sibling_eviction_level(Some(1));
+
+            fill_and_exercise(&mut plain, 2_000, &mut rng);
+            fill_and_exercise(&mut sibling, 2_000, &mut rng);
+
+            let upper_levels = plain.height as usize / 2 + 1;
+            let max_upper_fill = |fill: Vec<f64>| {
+                fill[..upper_levels]
+                    .iter()
+                    .fold(0.0f64, |acc, level_fill| acc.max(*level_fill))
+            };
+            let plain_fill = max_upper_fill(fill_by_level(&mut plain, &mut rng));
+            let sibling_fill = max_upper_fill(fill_by_level(&mut sibling, &mut rng));
+            assert!(
+                sibling_fill < plain_fill,
+                "sibling eviction did not balance the upper levels: {} vs {}",
+                sibling_fill,
+                plain_fill
+            );
+
+            // Data should still be correct after all of that
+            fill_and_exercise(&mut sibling, 2_000, &mut rng);
+        });
+    }
+
+    // Replay the same random writes through two ORAMs, checking that they agree,
+    // and record the stash occupancy of each after every access, side by side
+    fn compare_stash_occupancy(
+        lhs: &mut TestORAM,
+        rhs: &mut TestORAM,
+        num_rounds: usize,
+        rng: &mut RngType,
+    ) -> Vec<(u64, u64)> {
+        assert_eq!(lhs.len(), rhs.len());
+        let mut result = Vec::with_capacity(num_rounds);
+        for _ in 0..num_rounds {
+            let idx = rng.next_u64() % lhs.len();
+            let val = a64_bytes(rng.next_u32() as u8);
+            assert_eq!(lhs.write(idx, &val), rhs.write(idx, &val));
+            result.push((
+                details::ct_count_occupied(&lhs.stash_meta),
+                details::ct_count_occupied(&rhs.stash_meta),
+            ));
+        }
+        result
+    }
+
+    // Compare eviction only along the random accessed branch, against an extra
+    // round along the deterministic reverse-lexicographic schedule
+    #[test]
+    fn compare_random_and_deterministic_eviction() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut random = new_test_oram(&mut maker);
+            let mut deterministic = new_test_oram(&mut maker);
+            deterministic.set_eviction_rounds(2);
+
+            let comparison =
+                compare_stash_occupancy(&mut random, &mut deterministic, 3_000, &mut rng);
+            let max_random = comparison.iter().map(|(lhs, _)| *lhs).max().unwrap();
+            let max_deterministic = comparison.iter().map(|(_, rhs)| *rhs).max().unwrap();
+            assert!(max_random < 16, "random eviction filled the stash");
+            assert!(
+                max_deterministic < 16,
+                "deterministic eviction filled the stash"
+            );
+
+            let total_random: u64 = comparison.iter().map(|(lhs, _)| *lhs).sum();
+            let total_deterministic: u64 = comparison.iter().map(|(_, rhs)| *rhs).sum();
+            assert!(
+                total_deterministic <= total_random,
+                "extra eviction rounds did not reduce stash occupancy: {} vs {}",
+                total_deterministic,
+                total_random
+            );
+        });
+    }
+
+    // Test that try_access returns errors rather than panicking when the key is
+    // out of bounds or the position map is corrupted
+    #[test]
+    fn try_access_corrupt_position_map() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = new_test_oram(&mut maker);
+            assert_eq!(a64_bytes(0), oram.write(5, &a64_bytes(1)));
+            assert_eq!(a64_bytes(0), oram.write(6, &a64_bytes(2)));
+            assert_eq!(
+                oram.try_access(1024, |_| ()),
+                Err(ORAMError::KeyOutOfBounds)
+            );
+
+            // Corrupt the position map entry for key 5, with a node which is
+            // not a leaf, and then with a value that is too large to be a leaf
+            oram.pos.write(&5, &3);
+            assert_eq!(
+                oram.try_access(5, |val| *val),
+                Err(ORAMError::InvalidPosition)
+            );
+            oram.pos.write(&5, &(4u64 << oram.height));
+            assert_eq!(
+                oram.try_access(5, |val| *val),
+                Err(ORAMError::InvalidPosition)
+            );
+
+            // Other keys are unaffected
+            assert_eq!(oram.try_access(6, |val| *val), Ok(a64_bytes(2)));
+            assert_eq!(a64_bytes(2), oram.write(6, &a64_bytes(3)));
+            assert_eq!(oram.try_access(6, |val| *val), Ok(a64_bytes(3)));
+        });
+    }
+
+    // Test that with a subtree leaf assigner, blocks are only found in the
+    // branches through that subtree
+    #[test]
+    fn subtree_leaf_assigner_confines_blocks() {
+        use crate::SubtreeLeafAssigner;
+
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut oram = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                1024, 64, &mut maker,
+            );
+            let height = oram.height;
+            // Only the low 4 bits of the leaf are significant, the rest are 0b1011
+            let assigner = SubtreeLeafAssigner::with_significant_bits(height, 4, 0b1011);
+            let subtree = assigner.subtree();
+            assert_eq!(subtree, 0b11011);
+            // Blocks can pile up in the stash until their first access, since
+            // they start out on random branches, so the stash is large enough
+            // to hold all of them
+            oram.set_leaf_assigner(Box::new(assigner));
+
+            for key in 0..64 {
+                oram.write(key, &a64_bytes(key as u8));
+            }
+            for _ in 0..500 {
+                let key = rng.next_u64() % 64;
+                assert_eq!(oram.read(key), a64_bytes(key as u8));
+            }
+
+            // Every block is assigned a leaf in the subtree, and sits in a bucket
+            // which is an ancestor of the subtree root or inside the subtree
+            for leaf in (1u64 << height)..(2u64 << height) {
+                oram.branch.checkout(&mut oram.storage, leaf);
+                for (bucket_num, bucket_meta) in oram.branch.meta.iter().enumerate() {
+                    let bucket = leaf >> bucket_num;
+                    let bucket_meta: &[A8Bytes<MetaSize>] = bucket_meta.as_aligned_chunks();
+                    for meta in bucket_meta {
+                        if !bool::from(meta_is_vacant(meta)) {
+                            let item_leaf = *meta_leaf_num(meta);
+                            assert_eq!(item_leaf >> (height - subtree.height()), subtree);
+                            let in_subtree = bucket.height() >= subtree.height()
+                                && bucket >> (bucket.height() - subtree.height()) == subtree;
+                            let above_subtree = bucket.height() < subtree.height()
+                                && subtree >> (subtree.height() - bucket.height()) == bucket;
+                            assert!(in_subtree || above_subtree);
+                        }
+                    }
+                }
+                oram.branch.checkin(&mut oram.storage);
+            }
+        });
+    }
+
+    // Test that explicitly setting the uniform leaf assigner reproduces the
+    // default behavior exactly, given the same seeds
+    #[test]
+    fn uniform_leaf_assigner_matches_default() {
+        run_with_several_seeds(|rng| {
+            let mut default_maker = rng_maker(rng.clone());
+            let mut uniform_maker = rng_maker(rng);
+            let mut default_rng = default_maker();
+            let mut uniform_rng = uniform_maker();
+
+            let mut default_oram = new_test_oram(&mut default_maker);
+            let mut uniform_oram = new_test_oram(&mut uniform_maker);
+            uniform_oram.set_leaf_assigner(Box::new(UniformLeafAssigner));
+
+            fill_and_exercise(&mut default_oram, 500, &mut default_rng);
+            fill_and_exercise(&mut uniform_oram, 500, &mut uniform_rng);
+
+            for key in 0..1024 {
+                let new_pos = 1u64 << default_oram.height;
+                assert_eq!(
+                    default_oram.pos.write(&key, &new_pos),
+                    uniform_oram.pos.write(&key, &new_pos)
+                );
+            }
+            assert_eq!(
+                occupancy_by_level(&mut default_oram),
+                occupancy_by_level(&mut uniform_oram)
+            );
+        });
+    }
+
+    // Get the block numbers of all the items in the stash
+    fn stash_block_nums(oram: &TestORAM) -> Vec<u64> {
+        oram.stash_meta
+            .iter()
+            .filter(|meta| !bool::from(meta_is_vacant(meta)))
+            .map(meta_block_num)
+            .collect()
+    }
+
+    // Test that when blocks can't be evicted, the stash snapshot lists exactly them
+    #[test]
+    #[cfg(feature = "debug_stash")]
+    fn debug_stash_snapshot_lists_stuck_blocks() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut oram = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                1024, 64, &mut maker,
+            );
+            assert!(oram.debug_stash_snapshot().is_empty());
+
+            // The branch to this leaf holds 4 * (height + 1) blocks. Once it has
+            // been evicted into, any more are stuck in the stash
+            let leaf = 1u64.random_child_at_height(oram.height, &mut rng);
+            let capacity = 4 * (oram.height as u64 + 1);
+            oram.set_leaf_assigner(Box::new(FixedLeafAssigner(leaf)));
+            for key in 0..capacity + 10 {
+                oram.write(key, &a64_bytes(key as u8));
+            }
+            oram.evict_branch(leaf);
+
+            let mut snapshot = oram.debug_stash_snapshot();
+            snapshot.sort_unstable();
+            assert_eq!(snapshot.len(), 10);
+
+            // The stuck blocks are the written keys which are not in the branch
+            oram.branch.checkout(&mut oram.storage, leaf);
+            let mut in_branch = Vec::new();
+            for bucket_meta in oram.branch.meta.iter() {
+                let bucket_meta: &[A8Bytes<MetaSize>] = bucket_meta.as_aligned_chunks();
+                in_branch.extend(
+                    bucket_meta
+                        .iter()
+                        .filter(|meta| !bool::from(meta_is_vacant(meta)))
+                        .map(meta_block_num),
+                );
+            }
+            oram.branch.checkin(&mut oram.storage);
+            let expected: Vec<(u64, u64)> = (0..capacity + 10)
+                .filter(|key| !in_branch.contains(key))
+                .map(|key| (key, leaf))
+                .collect();
+            assert_eq!(snapshot, expected);
+        });
+    }
+
+    // Test that pinned keys stay in the stash, and unpinned keys drain normally
+    #[test]
+    fn pinned_keys_stay_in_stash() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut oram = new_test_oram(&mut maker);
+            for key in 0..4 {
+                oram.write(key, &a64_bytes(key as u8 + 1));
+                oram.set_pinned(key, true).unwrap();
+            }
+            for _ in 0..2_000 {
+                oram.read(4 + rng.next_u64() % 1020);
+                let stash = stash_block_nums(&oram);
+                for key in 0..4 {
+                    assert!(stash.contains(&key), "pinned key {} was evicted", key);
+                }
+            }
+            // Pinned values are still correct, and survive writes
+            for key in 0..4 {
+                assert_eq!(oram.write(key, &a64_bytes(10)), a64_bytes(key as u8 + 1));
+                assert!(stash_block_nums(&oram).contains(&key));
+            }
+
+            // Unpin two of the keys, and they should drain from the stash
+            oram.set_pinned(0, false).unwrap();
+            oram.set_pinned(1, false).unwrap();
+            for _ in 0..2_000 {
+                oram.read(4 + rng.next_u64() % 1020);
+            }
+            let stash = stash_block_nums(&oram);
+            assert!(!stash.contains(&0));
+            assert!(!stash.contains(&1));
+            assert!(stash.contains(&2));
+            assert!(stash.contains(&3));
+            for key in 0..4 {
+                assert_eq!(oram.read(key), a64_bytes(10));
+            }
+        });
+    }
+
+    // Test that a duplicated block changes the digest, rather than cancelling
+    // itself out, by copying a pinned block to a vacant stash slot
+    #[test]
+    fn digest_counts_duplicate_blocks() {
+        run_with_several_seeds(|rng| {
+            let mut oram = new_test_oram(&mut rng_maker(rng));
+            for key in 0..8 {
+                oram.write(key, &a64_bytes(key as u8 + 1));
+            }
+            oram.set_pinned(3, true).unwrap();
+            let occupied = |meta: &A8Bytes<MetaSize>| !bool::from(meta_is_vacant(meta));
+            let src = oram
+                .stash_meta
+                .iter()
+                .position(|meta| occupied(meta) && meta_block_num(meta) == 3)
+                .unwrap();
+            let dest = oram
+                .stash_meta
+                .iter()
+                .position(|meta| !occupied(meta))
+                .unwrap();
+            oram.stash_data[dest] = oram.stash_data[src];
+            oram.stash_meta[dest] = oram.stash_meta[src];
+            let duplicated = oram.digest();
+
+            oram.delete_where(|key, _| key == 3);
+            assert_ne!(duplicated, oram.digest());
+        });
+    }
+
+    // Test that a stash sweep drains formerly pinned keys into the tree in one
+    // pass, leaving pinned keys in place
+    #[test]
+    fn sweep_stash_drains_unpinned_keys() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = new_test_oram(&mut maker);
+            for key in 0..8 {
+                oram.write(key, &a64_bytes(key as u8 + 1));
+                oram.set_pinned(key, true).unwrap();
+            }
+            for key in 0..6 {
+                oram.set_pinned(key, false).unwrap();
+            }
+
+            oram.sweep_stash();
+            let stash = stash_block_nums(&oram);
+            assert_eq!(stash.len(), 2);
+            assert!(stash.contains(&6));
+            assert!(stash.contains(&7));
+            for key in 0..8 {
+                assert_eq!(oram.read(key), a64_bytes(key as u8 + 1));
+            }
+        });
+    }
+
+    // Test that after a sweep, the only blocks left in the stash are those whose
+    // branch is full, and that every value is still readable
+    #[test]
+    fn sweep_stash_leaves_only_unplaceable_blocks() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut oram = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                1024, 64, &mut maker,
+            );
+            // Write some blocks at random leaves, then more than fit in the
+            // branch to one leaf
+            for key in 0..100 {
+                oram.write(key, &a64_bytes(key as u8));
+            }
+            let leaf = 1u64.random_child_at_height(oram.height, &mut rng);
+            let capacity = 4 * (oram.height as u64 + 1);
+            oram.set_leaf_assigner(Box::new(FixedLeafAssigner(leaf)));
+            for key in 100..100 + capacity + 10 {
+                oram.write(key, &a64_bytes(key as u8));
+            }
+
+            oram.sweep_stash();
+            let stash = stash_block_nums(&oram);
+            assert!(stash.len() >= 10);
+            for meta in oram.stash_meta.iter() {
+                if !bool::from(meta_is_vacant(meta)) {
+                    assert_eq!(*meta_leaf_num(meta), leaf);
+                }
+            }
+            for bucket in leaf.parents() {
+                let bucket_meta = oram.read_bucket_meta(bucket);
+                assert!(!bool::from(bucket_has_empty_slot::<U4>(&bucket_meta)));
+            }
+
+            oram.set_leaf_assigner(Box::new(UniformLeafAssigner));
+            for key in 0..100 + capacity + 10 {
+                assert_eq!(oram.read(key), a64_bytes(key as u8));
+            }
+        });
+    }
+
+    // Test that compacting a fragmented stash makes the live items contiguous
+    // at the front, without changing any values
+    #[test]
+    fn compact_stash_is_contiguous() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = new_test_oram(&mut maker);
+            oram.set_stash_placement(StashPlacement::Spread);
+            for key in 0..8 {
+                oram.write(key, &a64_bytes(key as u8 + 1));
+                oram.set_pinned(key, true).unwrap();
+            }
+            for key in (0..8).step_by(2) {
+                oram.set_pinned(key, false).unwrap();
+            }
+            oram.sweep_stash();
+
+            let live = |oram: &TestORAM| -> Vec<bool> {
+                oram.stash_meta
+                    .iter()
+                    .map(|meta| !bool::from(meta_is_vacant(meta)))
+                    .collect()
+            };
+            let before = stash_block_nums(&oram);
+            assert_eq!(before.len(), 4);
+            assert_ne!(live(&oram)[..4], [true; 4]);
+
+            oram.compact_stash();
+            assert_eq!(stash_block_nums(&oram), before);
+            assert!(live(&oram)[..4].iter().all(|x| *x));
+            assert!(live(&oram)[4..].iter().all(|x| !*x));
+            for key in 0..8 {
+                assert_eq!(oram.read(key), a64_bytes(key as u8 + 1));
+            }
+        });
+    }
+
+    // Test that each stash placement strategy takes the documented slot
+    #[test]
+    fn insert_from_wraps_around() {
+        let mut dest_data: Vec<A64Bytes<U1024>> = vec![Default::default(); 4];
+        let mut dest_meta: Vec<A8Bytes<MetaSize>> = vec![Default::default(); 4];
+        dest_meta[3] = make_meta(9, 7);
+
+        // Front fill takes the first vacancy
+        let mut meta = make_meta(8, 1);
+        details::ct_insert(
+            1.into(),
+            &a64_bytes(1),
+            &mut meta,
+            &mut dest_data,
+            &mut dest_meta,
+        );
+        assert!(bool::from(meta_is_vacant(&meta)));
+        assert_eq!(dest_meta[0], make_meta(8, 1));
+
+        // Starting from slot 2 takes slot 2
+        let mut meta = make_meta(8, 2);
+        details::ct_insert_from(
+            2,
+            1.into(),
+            &a64_bytes(2),
+            &mut meta,
+            &mut dest_data,
+            &mut dest_meta,
+        );
+        assert_eq!(dest_meta[2], make_meta(8, 2));
+        assert_eq!(dest_data[2], a64_bytes(2));
+
+        // Starting from the occupied slot 3 wraps around past slot 0 to slot 1
+        let mut meta = make_meta(8, 3);
+        details::ct_insert_from(
+            3,
+            1.into(),
+            &a64_bytes(3),
+            &mut meta,
+            &mut dest_data,
+            &mut dest_meta,
+        );
+        assert_eq!(dest_meta[1], make_meta(8, 3));
+        assert_eq!(dest_meta[3], make_meta(9, 7));
+
+        // When full, nothing changes, and the item is not vacated
+        let mut meta = make_meta(8, 4);
+        let before = dest_meta.clone();
+        details::ct_insert_from(
+            1,
+            1.into(),
+            &a64_bytes(4),
+            &mut meta,
+            &mut dest_data,
+            &mut dest_meta,
+        );
+        assert_eq!(dest_meta, before);
+        assert_eq!(meta, make_meta(8, 4));
+    }
+
+    // Test that spread placement moves the starting slot on each access, and
+    // preserves correctness
+    #[test]
+    fn spread_stash_placement() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut oram = new_test_oram(&mut maker);
+            oram.set_stash_placement(StashPlacement::Spread);
+            assert_eq!(oram.next_stash_start(), 0);
+            assert_eq!(oram.next_stash_start(), 1);
+            fill_and_exercise(&mut oram, 2_000, &mut rng);
+            assert!(oram.stash_placement_counter > 1024);
+        });
+    }
+
+    // Make a stash metadata with a given leaf and block num
+    fn make_meta(leaf: u64, block_num: u64) -> A8Bytes<MetaSize> {
+        let mut result = A8Bytes::<MetaSize>::default();
+        *meta_leaf_num_mut(&mut result) = leaf;
+        *meta_block_num_mut(&mut result) = block_num;
+        result
+    }
+
+    // Test the conditional block num update, under both choices
+    #[test]
+    fn set_block_num_if() {
+        let mut meta = make_meta(9, 3);
+        meta_set_block_num_if(0.into(), &mut meta, 5);
+        assert_eq!(meta, make_meta(9, 3));
+        meta_set_block_num_if(1.into(), &mut meta, 5);
+        assert_eq!(meta, make_meta(9, 5));
+        assert!(!bool::from(meta_is_vacant(&meta)));
+
+        // The pin bit is kept
+        let mut pinned = make_meta(9, 3 | PIN_BIT);
+        meta_set_block_num_if(1.into(), &mut pinned, 6);
+        assert_eq!(meta_block_num(&pinned), 6);
+        assert!(bool::from(meta_is_pinned(&pinned)));
+
+        // A vacant metadata stays vacant
+        let mut vacant = make_meta(0, 0);
+        meta_set_block_num_if(1.into(), &mut vacant, 7);
+        assert_eq!(meta_block_num(&vacant), 7);
+        assert!(bool::from(meta_is_vacant(&vacant)));
+    }
+
+    // Test that meta_set_vacant zeroes the whole metadata, only if asked
+    #[test]
+    fn meta_set_vacant_zeroes_everything() {
+        let mut meta = make_meta(9, 3 | PIN_BIT);
+        meta_set_vacant(0.into(), &mut meta);
+        assert_eq!(meta, make_meta(9, 3 | PIN_BIT));
+        meta_set_vacant(1.into(), &mut meta);
+        assert_eq!(meta, A8Bytes::<MetaSize>::default());
+
+        // A slot emptied by ct_find_and_remove can be used as a set_meta_if source
+        let mut stash_data = vec![A64Bytes::<U64>::default(); 2];
+        let mut stash_meta = vec![make_meta(8, 1), make_meta(9, 2 | PIN_BIT)];
+        let mut dest_data = A64Bytes::<U64>::default();
+        let mut dest_meta = A8Bytes::<MetaSize>::default();
+        details::ct_find_and_remove(
+            1.into(),
+            &2,
+            &mut dest_data,
+            &mut dest_meta,
+            &mut stash_data,
+            &mut stash_meta,
+        );
+        assert_eq!(stash_meta[1], A8Bytes::<MetaSize>::default());
+        let mut other = make_meta(7, 4);
+        set_meta_if(0.into(), &mut other, &stash_meta[1]);
+        assert_eq!(other, make_meta(7, 4));
+    }
+
+    // Test that set_meta_if overwrites the whole metadata only if asked
+    #[test]
+    fn set_meta_if_both_choices() {
+        let mut dest = make_meta(8, 1);
+        set_meta_if(0.into(), &mut dest, &make_meta(9, 2 | PIN_BIT));
+        assert_eq!(dest, make_meta(8, 1));
+        set_meta_if(1.into(), &mut dest, &make_meta(9, 2 | PIN_BIT));
+        assert_eq!(dest, make_meta(9, 2 | PIN_BIT));
+        set_meta_if(1.into(), &mut dest, &Default::default());
+        assert_eq!(dest, A8Bytes::<MetaSize>::default());
+    }
+
+    // Test that set_meta_if rejects half-vacant metadata in debug builds, even
+    // if the condition is false
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "metadata is half-vacant")]
+    fn set_meta_if_rejects_half_vacant() {
+        let mut dest = make_meta(8, 1);
+        set_meta_if(0.into(), &mut dest, &make_meta(0, 5));
+    }
+
+    // Test that init_vacant makes every slot of a bucket vacant
+    #[test]
+    fn init_vacant_bucket() {
+        let mut bucket_meta = A8Bytes::<Prod<U4, MetaSize>>::default();
+        {
+            let slots: &mut [A8Bytes<MetaSize>] = bucket_meta.as_mut_aligned_chunks();
+            for (idx, slot) in slots.iter_mut().enumerate() {
+                *slot = make_meta(8 + idx as u64, idx as u64 | PIN_BIT);
+            }
+        }
+        assert!(!bool::from(bucket_has_empty_slot::<U4>(&bucket_meta)));
+
+        init_vacant::<U4>(&mut bucket_meta);
+        assert!(bool::from(bucket_has_empty_slot::<U4>(&bucket_meta)));
+        let slots: &[A8Bytes<MetaSize>] = bucket_meta.as_aligned_chunks();
+        for slot in slots {
+            assert!(bool::from(meta_is_vacant(slot)));
+            assert!(!bool::from(meta_is_pinned(slot)));
+        }
+
+        // A single vacancy is enough
+        let slots: &mut [A8Bytes<MetaSize>] = bucket_meta.as_mut_aligned_chunks();
+        for slot in slots[1..].iter_mut() {
+            *slot = make_meta(9, 1);
+        }
+        assert!(bool::from(bucket_has_empty_slot::<U4>(&bucket_meta)));
+    }
+
+    // Test that ct_swap_slots swaps data and metadata together, only if asked
+    #[test]
+    fn swap_slots() {
+        let mut stash_data: A64Bytes<U1024> = a64_bytes(1);
+        let mut stash_meta = make_meta(8, 1);
+        let mut bucket_data: A64Bytes<U1024> = a64_bytes(2);
+        let mut bucket_meta = make_meta(9, 2);
+
+        details::ct_swap_slots(
+            0.into(),
+            &mut stash_data,
+            &mut stash_meta,
+            &mut bucket_data,
+            &mut bucket_meta,
+        );
+        assert_eq!(stash_data, a64_bytes(1));
+        assert_eq!(stash_meta, make_meta(8, 1));
+        assert_eq!(bucket_data, a64_bytes(2));
+        assert_eq!(bucket_meta, make_meta(9, 2));
+
+        details::ct_swap_slots(
+            1.into(),
+            &mut stash_data,
+            &mut stash_meta,
+            &mut bucket_data,
+            &mut bucket_meta,
+        );
+        assert_eq!(stash_data, a64_bytes(2));
+        assert_eq!(stash_meta, make_meta(9, 2));
+        assert_eq!(bucket_data, a64_bytes(1));
+        assert_eq!(bucket_meta, make_meta(8, 1));
+    }
+
+    // Test that ct_take_for_bucket takes the first item which fits, and frees its slot
+    #[test]
+    fn take_for_bucket() {
+        // Branch leaf is 8 = 0b1000, at height 3. Its buckets are 8, 4, 2, 1.
+        let mut stash_data: Vec<A64Bytes<U1024>> = (0..5).map(a64_bytes).collect();
+        let mut stash_meta = vec![
+            make_meta(0, 0),
+            make_meta(15, 1),
+            make_meta(9, 2),
+            make_meta(8, 3),
+            make_meta(8, 4),
+        ];
+
+        // Nothing else can go in the leaf bucket, and the first match is taken
+        let (found, data, meta) =
+            details::ct_take_for_bucket(1.into(), 0, 8, &mut stash_data, &mut stash_meta);
+        assert!(bool::from(found));
+        assert_eq!(data, a64_bytes(3));
+        assert_eq!(meta, make_meta(8, 3));
+        assert!(bool::from(meta_is_vacant(&stash_meta[3])));
+        assert_eq!(stash_meta[4], make_meta(8, 4));
+
+        // Bucket 4 can hold leaves 8 and 9, but not 15
+        let (found, data, meta) =
+            details::ct_take_for_bucket(1.into(), 1, 8, &mut stash_data, &mut stash_meta);
+        assert!(bool::from(found));
+        assert_eq!(data, a64_bytes(2));
+        assert_eq!(meta, make_meta(9, 2));
+        assert!(bool::from(meta_is_vacant(&stash_meta[2])));
+
+        // If the condition is false, or nothing fits, nothing is taken or revealed
+        let stash_meta_before = stash_meta.clone();
+        let (found, data, meta) =
+            details::ct_take_for_bucket(0.into(), 3, 8, &mut stash_data, &mut stash_meta);
+        assert!(!bool::from(found));
+        assert_eq!(data, a64_bytes(0));
+        assert!(bool::from(meta_is_vacant(&meta)));
+        assert_eq!(stash_meta, stash_meta_before);
+
+        // Mark the last item pinned, now only leaf 15 is left and it can't go in 4
+        *meta_block_num_mut(&mut stash_meta[4]) |= PIN_BIT;
+        let stash_meta_before = stash_meta.clone();
+        let (found, data, meta) =
+            details::ct_take_for_bucket(1.into(), 1, 8, &mut stash_data, &mut stash_meta);
+        assert!(!bool::from(found));
+        assert_eq!(data, a64_bytes(0));
+        assert!(bool::from(meta_is_vacant(&meta)));
+        assert_eq!(stash_meta, stash_meta_before);
+
+        // But it can go in the root
+        let (found, data, meta) =
+            details::ct_take_for_bucket(1.into(), 3, 8, &mut stash_data, &mut stash_meta);
+        assert!(bool::from(found));
+        assert_eq!(data, a64_bytes(1));
+        assert_eq!(meta, make_meta(15, 1));
+        assert!(stash_meta
+            .iter()
+            .all(|meta| bool::from(meta_is_vacant(meta) | meta_is_pinned(meta))));
+    }
+
+    // Test that the eviction records match the branches which were actually
+    // evicted into, and the state of the stash and storage afterwards
+    #[cfg(feature = "eviction_log")]
+    #[test]
+    fn eviction_records_match_decisions() {
+        extern crate std;
+        use std::sync::Mutex;
+
+        run_with_several_seeds(|rng| {
+            let mut oram = new_recording_oram(rng);
+            oram.set_eviction_rounds(2);
+            let records = Arc::new(Mutex::new(Vec::<EvictionRecord>::new()));
+            let sink_records = records.clone();
+            oram.set_eviction_sink(Some(Box::new(move |record| {
+                sink_records.lock().unwrap().push(record.clone())
+            })));
+            for key in 0..300 {
+                oram.write(key, &a64_bytes(key as u8));
+            }
+
+            // Every checkin during the workload is of an evicted branch
+            let records = records.lock().unwrap();
+            assert_eq!(records.len(), 600);
+            let checkins: Vec<u64> = oram
+                .storage
+                .log
+                .iter()
+                .filter(|(op, _)| *op == "checkin")
+                .map(|(_, leaf)| *leaf)
+                .collect();
+            let leaves: Vec<u64> = records.iter().map(|record| record.leaf).collect();
+            assert_eq!(leaves, checkins);
+
+            for record in records.iter() {
+                assert_eq!(record.level_loads.len(), oram.height as usize + 1);
+                assert!(record.stash_after <= record.stash_before + 1);
+            }
+            let last = records.last().unwrap();
+            assert_eq!(
+                last.stash_after,
+                details::ct_count_occupied(&oram.stash_meta)
+            );
+            let loads: Vec<u64> = (0..=oram.height)
+                .map(|level| {
+                    let bucket_meta = oram.read_bucket_meta(last.leaf >> (oram.height - level));
+                    details::ct_count_occupied(bucket_meta.as_aligned_chunks())
+                })
+                .collect();
+            assert_eq!(last.level_loads, loads);
+        });
+    }
+
+    // Test that saving the schedule position, and restoring it in a new ORAM,
+    // resumes the same sequence of extra eviction branches
+    #[test]
+    fn schedule_position_resumes() {
+        run_with_one_seed(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut oram = new_test_oram(&mut maker);
+            oram.set_eviction_rounds(2);
+            assert_eq!(oram.schedule_position(), 0);
+            fill_and_exercise(&mut oram, 100, &mut rng);
+            let position = oram.schedule_position();
+            // One extra round per access, after the 1024 writes which fill it
+            assert_eq!(position, 1024 + 100);
+
+            let mut restored = new_test_oram(&mut maker);
+            restored.set_schedule_position(position);
+            assert_eq!(restored.schedule_position(), position);
+            for _ in 0..100 {
+                assert_eq!(oram.next_extra_leaf(), restored.next_extra_leaf());
+            }
+        });
+    }
+
+    // Test the branch length helpers, and that they match checked-out branches
+    #[test]
+    fn branch_len_matches_checkout() {
+        assert_eq!(branch_len(0), 1);
+        assert_eq!(branch_len(1), 2);
+        assert_eq!(branch_len(10), 11);
+        assert_eq!(branch_len(62), 63);
+        assert_eq!(stash_adjusted_len(0), 2);
+        assert_eq!(stash_adjusted_len(10), 12);
+
+        run_with_one_seed(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = new_test_oram(&mut maker);
+            let leaf = 1u64 << oram.height;
+            oram.branch.checkout(&mut oram.storage, leaf);
+            assert_eq!(oram.branch.data.len(), branch_len(oram.height));
+            assert_eq!(oram.branch.meta.len(), branch_len(oram.height));
+            oram.branch.checkin(&mut oram.storage);
+        });
+    }
+
+    // Create a small ORAM with a given stash size and overflow policy
+    fn overflowing_oram(rng: RngType, stash_size: usize, policy: OverflowPolicy) -> TestORAM {
+        TestORAM::new_with_overflow_policy::<
+            U32PositionMapCreator<
+                U1024,
+                RngType,
+                PathORAM4096Z4Creator<RngType, HeapORAMStorageCreator>,
+            >,
+            HeapORAMStorageCreator,
+            _,
+        >(256, stash_size, policy, &mut rng_maker(rng))
+    }
+
+    // Test that the stash size given at construction is reported, and bounds
+    // the stash occupancy, including when it overflows
+    #[test]
+    fn stash_size_is_honored() {
+        run_with_several_seeds(|rng| {
+            for stash_size in [0usize, 1, 4].iter() {
+                let mut oram =
+                    overflowing_oram(rng.clone(), *stash_size, OverflowPolicy::ReturnError);
+                assert_eq!(oram.stash_size(), *stash_size);
+                let mut num_overflows = 0;
+                for key in 0..256 {
+                    if oram.try_access(key, |val| *val = a64_bytes(1)).is_err() {
+                        num_overflows += 1;
+                    }
+                    let occupied = details::ct_count_occupied(&oram.stash_meta);
+                    assert!(occupied <= *stash_size as u64);
+                }
+                assert_eq!(oram.stash_size(), *stash_size);
+                if *stash_size == 0 {
+                    assert_eq!(num_overflows, 256);
+                }
+            }
+        });
+    }
+
+    // Test that the default overflow policy panics
+    #[test]
+    #[should_panic(expected = "Stash overflow!")]
+    fn overflow_policy_panic() {
+        use rand_core::SeedableRng;
+        let mut oram = overflowing_oram(RngType::from_seed([7u8; 32]), 0, OverflowPolicy::Panic);
+        oram.write(1, &a64_bytes(1));
+    }
+
+    // Test that the return-error overflow policy reports the overflow, but
+    // keeps the block and the change made to it
+    #[test]
+    fn overflow_policy_return_error() {
+        run_with_several_seeds(|rng| {
+            let mut oram = overflowing_oram(rng, 0, OverflowPolicy::ReturnError);
+            assert_eq!(
+                oram.try_access(1, |val| *val = a64_bytes(1)),
+                Err(ORAMError::StashOverflow)
+            );
+            assert_eq!(oram.spill_len(), 1);
+
+            // The block is still there, though every access to it overflows
+            let mut seen = a64_bytes(0);
+            assert_eq!(
+                oram.try_access(1, |val| {
+                    seen = *val;
+                    *val = a64_bytes(2);
+                }),
+                Err(ORAMError::StashOverflow)
+            );
+            assert_eq!(seen, a64_bytes(1));
+            assert_eq!(
+                oram.try_access(1, |val| seen = *val),
+                Err(ORAMError::StashOverflow)
+            );
+            assert_eq!(seen, a64_bytes(2));
+            assert_eq!(oram.spill_len(), 1);
+        });
+    }
+
+    // Test that with a stash, blocks which overflowed under the return-error
+    // policy move back into the stash, and no block is lost
+    #[test]
+    fn overflow_policy_return_error_recovers() {
+        run_with_several_seeds(|rng| {
+            let mut oram = overflowing_oram(rng, 1, OverflowPolicy::ReturnError);
+            let mut num_overflows = 0;
+            for key in 0..256 {
+                let value = a64_bytes((key % 255) as u8 + 1);
+                if oram.try_access(key, |val| *val = value).is_err() {
+                    num_overflows += 1;
+                }
+            }
+            assert!(num_overflows > 0);
+            for key in 0..256 {
+                let mut seen = a64_bytes(0);
+                let _ = oram.try_access(key, |val| seen = *val);
+                assert_eq!(seen, a64_bytes((key % 255) as u8 + 1));
+            }
+            // The spill area drains as blocks are evicted from the stash
+            for _ in 0..256 {
+                if oram.spill_len() == 0 {
+                    break;
+                }
+                let _ = oram.try_access(0, |_| ());
+            }
+            assert_eq!(oram.spill_len(), 0);
+        });
+    }
+
+    // Test that the spill overflow policy keeps blocks in the spill area
+    #[test]
+    fn overflow_policy_spill() {
+        run_with_several_seeds(|rng| {
+            let mut oram = overflowing_oram(rng, 0, OverflowPolicy::SpillToSecondary);
+            for key in 0..8 {
+                oram.write(key, &a64_bytes(key as u8 + 1));
+            }
+            assert_eq!(oram.spill_len(), 8);
+            for key in 0..8 {
+                assert_eq!(oram.read(key), a64_bytes(key as u8 + 1));
+            }
+        });
+    }
+
+    // Test that eviction neither duplicates nor loses blocks, for each strategy
+    #[test]
+    fn eviction_conserves_blocks() {
+        for name in EVICTION_STRATEGY_NAMES.iter() {
+            run_with_several_seeds(|rng| {
+                let mut maker = rng_maker(rng);
+                let mut rng = maker();
+                let mut oram = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                    256, 16, &mut maker,
+                );
+                oram.set_eviction_strategy(crate::eviction_strategy_by_name(name).unwrap());
+                for _ in 0..200 {
+                    let key = rng.next_u64() % 256;
+                    oram.write(key, &a64_bytes(rng.next_u32() as u8));
+
+                    let leaf = (1u64 << oram.height) | (rng.next_u64() % (1u64 << oram.height));
+                    oram.branch.checkout(&mut oram.storage, leaf);
+                    let before = oram.count_live_blocks();
+                    oram.evict_into_branch();
+                    assert_eq!(before, oram.count_live_blocks());
+                    oram.branch.check_invariants(&oram.stash_meta);
+                    oram.branch.checkin(&mut oram.storage);
+                }
+            });
+        }
+    }
+
+    // Modify a bucket of the storage behind the ORAM's back
+    fn tamper_with_storage(oram: &mut TestORAM, leaf: u64, bucket_num: usize) {
+        let checksum = oram.branch.checksum.take();
+        oram.branch.checkout(&mut oram.storage, leaf);
+        oram.branch.data[bucket_num][0] ^= 1;
+        oram.branch.checkin(&mut oram.storage);
+        oram.branch.checksum = checksum;
+    }
+
+    // Test that the running checksum detects out-of-band changes to storage
+    #[test]
+    fn checksum_detects_tampering() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut oram = new_test_oram(&mut maker);
+            fill_and_exercise(&mut oram, 200, &mut rng);
+            oram.enable_checksum();
+            assert!(oram.verify_checksum());
+            fill_and_exercise(&mut oram, 200, &mut rng);
+            assert!(oram.verify_checksum());
+
+            // Tampering with a bucket is detected
+            let leaf = 1u64.random_child_at_height(oram.height, &mut rng);
+            tamper_with_storage(&mut oram, leaf, 1);
+            assert!(!oram.verify_checksum());
+
+            // Even if the tampered bucket is then read and rewritten
+            oram.enable_checksum();
+            assert!(oram.verify_checksum());
+            tamper_with_storage(&mut oram, leaf, 0);
+            oram.evict_branch(leaf);
+            assert!(!oram.verify_checksum());
+        });
+    }
+
+    type ParityStorage = ParityORAMStorage<U4096, U64, HeapORAMStorage<U4096, U64>>;
+
+    // Corrupt a bucket of the storage under the parity behind the ORAM's back
+    fn corrupt_under_parity(
+        oram: &mut PathORAM<U1024, U4, ParityStorage, RngType>,
+        leaf: u64,
+        bucket_num: usize,
+    ) {
+        let inner = oram.storage.inner_mut();
+        oram.branch.checkout(inner, leaf);
+        oram.branch.data[bucket_num][0] ^= 1;
+        oram.branch.meta[bucket_num][0] ^= 1;
+        oram.branch.checkin(inner);
+    }
+
+    // Test that a corrupted bucket is reconstructed from its parity and
+    // sibling, including the root, so that reads are still correct
+    #[test]
+    fn parity_storage_repairs_one_bucket() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut oram = PathORAM4096Z4Creator::<
+                RngType,
+                ParityORAMStorageCreator<HeapORAMStorageCreator>,
+            >::create(1024, 16, &mut maker);
+            let mut expected = vec![0u8; 1024];
+            for key in 0..1024 {
+                let val = rng.next_u32() as u8;
+                oram.write(key, &a64_bytes(val));
+                expected[key as usize] = val;
+            }
+
+            // One bucket of each of several pairs, at every level
+            let height = oram.height;
+            for level in 0..=height {
+                let leaf = 1u64.random_child_at_height(height, &mut rng);
+                corrupt_under_parity(&mut oram, leaf, (height - level) as usize);
+            }
+            for key in 0..1024 {
+                assert_eq!(oram.read(key), a64_bytes(expected[key as usize]));
+            }
+            // The repairs were written back
+            for leaf in (1u64 << height)..(2u64 << height) {
+                oram.evict_branch(leaf);
+            }
+            for key in 0..1024 {
+                assert_eq!(oram.read(key), a64_bytes(expected[key as usize]));
+            }
+        });
+    }
+
+    // Test that the bucket metadata read for tooling is repaired, like a
+    // checkout, rather than read raw from the corrupted storage
+    #[test]
+    fn parity_storage_repairs_bucket_meta() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = PathORAM4096Z4Creator::<
+                RngType,
+                ParityORAMStorageCreator<HeapORAMStorageCreator>,
+            >::create(1024, 16, &mut maker);
+            for key in 0..1024 {
+                oram.write(key, &a64_bytes(key as u8));
+            }
+            let leaf = 1u64 << oram.height;
+            let expected = oram.read_bucket_meta(leaf >> 1);
+            corrupt_under_parity(&mut oram, leaf, 1);
+            assert_eq!(oram.read_bucket_meta(leaf >> 1), expected);
+        });
+    }
+
+    // Test that corrupting both buckets of a pair can't be repaired
+    #[test]
+    #[should_panic(expected = "unrecoverable corruption")]
+    fn parity_storage_fails_on_corrupted_pair() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = PathORAM4096Z4Creator::<
+                RngType,
+                ParityORAMStorageCreator<HeapORAMStorageCreator>,
+            >::create(1024, 16, &mut maker);
+            for key in 0..1024 {
+                oram.write(key, &a64_bytes(key as u8));
+            }
+            let leaf = 1u64 << oram.height;
+            corrupt_under_parity(&mut oram, leaf, 0);
+            corrupt_under_parity(&mut oram, leaf + 1, 0);
+            oram.evict_branch(leaf);
+        });
+    }
+
+    // Test the rendering of a branch with a known layout
+    #[test]
+    #[cfg(feature = "debug_branch")]
+    fn format_branch_shows_known_branch() {
+        run_with_one_seed(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram =
+                PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(16, 4, &mut maker);
+            // 16 items with Z = 4 gives height 2, so leaf 5 has buckets 5, 2, 1
+            assert_eq!(oram.height, 2);
+            oram.branch.checkout(&mut oram.storage, 5);
+            {
+                let leaf_bucket: &mut [A8Bytes<MetaSize>] =
+                    oram.branch.meta[0].as_mut_aligned_chunks();
+                leaf_bucket[0] = make_meta(5, 7);
+                leaf_bucket[2] = make_meta(5, 3 | PIN_BIT);
+            }
+            {
+                let root_bucket: &mut [A8Bytes<MetaSize>] =
+                    oram.branch.meta[2].as_mut_aligned_chunks();
+                root_bucket[1] = make_meta(6, 11);
+            }
+            oram.branch.checkin(&mut oram.storage);
+
+            assert_eq!(
+                oram.format_branch(5),
+                concat!(
+                    "branch to leaf 5\n",
+                    "bucket 1: [-] [11 -> 6] [-] [-]\n",
+                    "  bucket 2: [-] [-] [-] [-]\n",
+                    "    bucket 5: [7 -> 5] [-] [3* -> 5] [-]\n",
+                )
+            );
+        });
+    }
+
+    // Test that overriding the eviction rounds for one access drains the stash
+    // faster than the default
+    #[test]
+    fn burst_eviction_drains_stash() {
+        use crate::PathORAM4096Z2Creator;
+        use aligned_cmov::typenum::U2048;
+
+        run_with_several_seeds(|rng| {
+            // Two ORAMs which behave identically until the burst
+            let mut default_maker = rng_maker(rng.clone());
+            let mut burst_maker = rng_maker(rng);
+            let mut default_oram = PathORAM4096Z2Creator::<RngType, HeapORAMStorageCreator>::create(
+                1024,
+                64,
+                &mut default_maker,
+            );
+            let mut burst_oram = PathORAM4096Z2Creator::<RngType, HeapORAMStorageCreator>::create(
+                1024,
+                64,
+                &mut burst_maker,
+            );
+            let mut rng = default_maker();
+
+            // Fill with Z = 2, which leaves a lot in the stash
+            for key in 0..1024 {
+                default_oram.write(key, &a64_bytes::<U2048>(key as u8));
+                burst_oram.write(key, &a64_bytes::<U2048>(key as u8));
+            }
+            let before = details::ct_count_occupied(&default_oram.stash_meta);
+            assert_eq!(before, details::ct_count_occupied(&burst_oram.stash_meta));
+            assert!(before > 0);
+
+            let key = rng.next_u64() % 1024;
+            let default_val = default_oram.try_access(key, |val| val[0]).unwrap();
+            let burst_val = burst_oram
+                .try_access_with_eviction_rounds(key, 64, |val| val[0])
+                .unwrap();
+            assert_eq!(default_val, key as u8);
+            assert_eq!(burst_val, default_val);
+            assert!(
+                details::ct_count_occupied(&burst_oram.stash_meta)
+                    < details::ct_count_occupied(&default_oram.stash_meta)
+            );
+
+            // The override only applies to that one call
+            assert_eq!(burst_oram.eviction_rounds, 1);
+        });
+    }
+
+    // Test the order of the leaves used for extra eviction rounds
+    #[test]
+    fn reverse_lex_order() {
+        let leaves: Vec<u64> = (0..8).map(|idx| reverse_lex_leaf(idx, 3)).collect();
+        assert_eq!(leaves, vec![8, 12, 10, 14, 9, 13, 11, 15]);
+        assert_eq!(reverse_lex_leaf(8, 3), 8);
+        assert_eq!(reverse_lex_leaf(5, 0), 1);
+    }
+
+    // Test that the precomputed schedule visits every leaf once, in the same
+    // order as the incremental eviction schedule
+    #[test]
+    fn reverse_lex_schedule_matches_incremental() {
+        assert_eq!(reverse_lex_schedule(0), vec![1]);
+        for height in 1..10 {
+            let schedule = reverse_lex_schedule(height);
+            assert_eq!(schedule.len(), 1usize << height);
+            let mut sorted = schedule.clone();
+            sorted.sort_unstable();
+            let leaves: Vec<u64> = ((1u64 << height)..(2u64 << height)).collect();
+            assert_eq!(sorted, leaves);
+        }
+
+        run_with_one_seed(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = new_test_oram(&mut maker);
+            let schedule = reverse_lex_schedule(oram.height);
+            for leaf in schedule.iter() {
+                assert_eq!(oram.next_reverse_lex_leaf(), *leaf);
+            }
+            // The schedule repeats
+            assert_eq!(oram.next_reverse_lex_leaf(), schedule[0]);
+        });
+    }
+
+    // Test that the depth-first selector takes the leaves of each subtree
+    // before moving right, and every leaf once per period
+    #[test]
+    fn depth_first_selector_order() {
+        let leaves: Vec<u64> = (0..8).map(|idx| depth_first_leaf(idx, 3)).collect();
+        assert_eq!(leaves, vec![8, 9, 10, 11, 12, 13, 14, 15]);
+        assert_eq!(depth_first_leaf(8, 3), 8);
+        assert_eq!(depth_first_leaf(5, 0), 1);
+
+        run_with_one_seed(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = new_test_oram(&mut maker);
+            oram.set_branch_selector(BranchSelector::DepthFirst);
+            let height = oram.height;
+            let period = 1u64 << height;
+            for _ in 0..2 {
+                let leaves: Vec<u64> = (0..period).map(|_| oram.next_extra_leaf()).collect();
+                let expected: Vec<u64> = (period..2 * period).collect();
+                assert_eq!(leaves, expected);
+                // Each subtree is finished before the next one is started
+                for level in 1..=height {
+                    let subtrees: Vec<u64> =
+                        leaves.iter().map(|leaf| leaf >> (height - level)).collect();
+                    assert!(subtrees.windows(2).all(|pair| pair[0] <= pair[1]));
+                }
+            }
+        });
+    }
+
+    // Test that more eviction rounds per access reduce stash residency,
+    // and don't affect correctness
+    #[test]
+    fn eviction_rounds_reduce_stash_residency() {
+        use crate::PathORAM4096Z2Creator;
+        use aligned_cmov::typenum::U2048;
+
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut residency = Vec::new();
+            for rounds in [1, 2, 4].iter() {
+                // Z = 2 is prone to stash growth, which makes the effect visible
+                let mut oram = PathORAM4096Z2Creator::<RngType, HeapORAMStorageCreator>::create(
+                    1024, 64, &mut maker,
+                );
+                oram.set_eviction_rounds(*rounds);
+                let mut expected = vec![0u8; 1024];
+                let mut total = 0;
+                for round in 0..3000 {
+                    let idx = if round < 1024 {
+                        round
+                    } else {
+                        rng.next_u64() % 1024
+                    };
+                    let val = rng.next_u32() as u8;
+                    let old = oram.write(idx, &a64_bytes::<U2048>(val));
+                    assert_eq!(old, a64_bytes(expected[idx as usize]));
+                    expected[idx as usize] = val;
+                    total += details::ct_count_occupied(&oram.stash_meta);
+                }
+                residency.push(total);
+            }
+            assert!(
+                residency[0] > residency[1] && residency[1] >= residency[2],
+                "{:?}",
+                residency
+            );
+        });
+    }
+
+    // Test that when accesses are confined to part of the tree, the stalest
+    // subtree selector evens out evictions across subtrees, and reverse-lex
+    // does not
+    #[test]
+    fn stalest_subtree_balances_eviction() {
+        use crate::SubtreeLeafAssigner;
+
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut spreads = Vec::new();
+            for selector in [BranchSelector::ReverseLex, BranchSelector::StalestSubtree].iter() {
+                let mut oram = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                    1024, 32, &mut maker,
+                );
+                // Blocks only live under the left child of the root
+                oram.set_leaf_assigner(Box::new(SubtreeLeafAssigner::new(2)));
+                oram.set_eviction_rounds(2);
+                oram.set_branch_selector(*selector);
+                let mut expected = [0u8; 128];
+                for _ in 0..1000 {
+                    let idx = rng.next_u64() % 128;
+                    let val = rng.next_u32() as u8;
+                    assert_eq!(
+                        oram.write(idx, &a64_bytes(val)),
+                        a64_bytes(expected[idx as usize])
+                    );
+                    expected[idx as usize] = val;
+                }
+                let counts = &oram.subtree_evictions;
+                assert_eq!(counts.len(), 8);
+                let spread = counts.iter().max().unwrap() - counts.iter().min().unwrap();
+                spreads.push(spread);
+            }
+            // Accessed branches alone give about 250 evictions per left subtree
+            assert!(spreads[0] > 100, "{:?}", spreads);
+            assert!(spreads[1] < spreads[0] / 4, "{:?}", spreads);
+        });
+    }
+
+    // Test that the layout invariants hold for the configurations we ship,
+    // and that valid configurations construct
+    #[test]
+    fn layout_invariants() {
+        use aligned_cmov::typenum::{U2, U2048, U256, U32};
+        TestORAM::assert_layout();
+        PathORAM::<U2048, U2, HeapORAMStorage<U4096, U32>, RngType>::assert_layout();
+        PathORAM::<U64, U4, HeapORAMStorage<U256, U64>, RngType>::assert_layout();
+
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = PathORAM4096Z4Creator::<RngType, HeapORAMStorageCreator>::create(
+                64, 16, &mut maker,
+            );
+            assert_eq!(oram.read(3), A64Bytes::<U1024>::default());
+        });
+    }
+
+    // A position map which checks and counts how PathORAM calls it
+    struct MockPositionMap {
+        data: Vec<u64>,
+        height: u32,
+        rng: RngType,
+        num_writes: Arc<AtomicU64>,
+    }
+
+    impl PositionMap for MockPositionMap {
+        fn len(&self) -> u64 {
+            self.data.len() as u64
+        }
+        fn write(&mut self, key: &u64, new_val: &u64) -> u64 {
+            assert!(*key < self.len(), "key out of bounds");
+            assert_eq!(*new_val >> self.height, 1, "new value is not a leaf");
+            self.num_writes.fetch_add(1, Ordering::SeqCst);
+            let mut old_val = core::mem::replace(&mut self.data[*key as usize], *new_val);
+            if old_val == 0 {
+                old_val = 1u64.random_child_at_height(self.height, &mut self.rng);
+            }
+            old_val
+        }
+    }
+
+    // Test that PathORAM can be built on a user-supplied position map, calls it
+    // once per access, and reads remain accurate
+    #[test]
+    fn mock_position_map() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let num_writes = Arc::new(AtomicU64::new(0));
+            let pos = MockPositionMap {
+                data: vec![0; 1024],
+                height: TestORAM::height_for_size(1024),
+                rng: maker(),
+                num_writes: num_writes.clone(),
+            };
+            let mut oram = TestORAM::new_with_position_map::<HeapORAMStorageCreator, _>(
+                Box::new(pos),
+                16,
+                &mut maker,
+            );
+            assert_eq!(oram.len(), 1024);
+
+            fill_and_exercise(&mut oram, 1_000, &mut rng);
+            assert_eq!(num_writes.load(Ordering::SeqCst), 1024 + 1_000);
+        });
+    }
+
+    // A storage which records the leaf of every checkout and checkin
+    struct RecordingStorage {
+        inner: HeapORAMStorage<U4096, U64>,
+        log: Vec<(&'static str, u64)>,
+        // The number of checkins, which outlives the storage
+        checkins: Arc<AtomicU64>,
+    }
+
+    impl ORAMStorage<U4096, U64> for RecordingStorage {
+        fn len(&self) -> u64 {
+            self.inner.len()
+        }
+        fn checkout(
+            &mut self,
+            leaf_index: u64,
+            dest: &mut [A64Bytes<U4096>],
+            dest_meta: &mut [A8Bytes<U64>],
+        ) {
+            self.log.push(("checkout", leaf_index));
+            self.inner.checkout(leaf_index, dest, dest_meta)
+        }
+        fn checkin(
+            &mut self,
+            leaf_index: u64,
+            src: &mut [A64Bytes<U4096>],
+            src_meta: &mut [A8Bytes<U64>],
+        ) {
+            self.log.push(("checkin", leaf_index));
+            self.checkins.fetch_add(1, Ordering::SeqCst);
+            self.inner.checkin(leaf_index, src, src_meta)
+        }
+    }
+
+    struct RecordingStorageCreator;
+
+    impl ORAMStorageCreator<U4096, U64> for RecordingStorageCreator {
+        type Output = RecordingStorage;
+        type Error = <HeapORAMStorageCreator as ORAMStorageCreator<U4096, U64>>::Error;
+
+        fn create<R: RngCore + CryptoRng>(
+            size: u64,
+            rng: &mut R,
+        ) -> Result<Self::Output, Self::Error> {
+            Ok(RecordingStorage {
+                inner: HeapORAMStorageCreator::create(size, rng)?,
+                log: Vec::new(),
+                checkins: Default::default(),
+            })
+        }
+    }
+
+    type RecordingORAM = PathORAM<U1024, U4, RecordingStorage, RngType>;
+
+    // Make an ORAM on recording storage, where each key is placed at a random
+    // leaf on its first access
+    fn new_recording_oram(rng: RngType) -> RecordingORAM {
+        let mut maker = rng_maker(rng);
+        let pos = MockPositionMap {
+            data: vec![0; 1024],
+            height: RecordingORAM::height_for_size(1024),
+            rng: maker(),
+            num_writes: Default::default(),
+        };
+        RecordingORAM::new_with_position_map::<RecordingStorageCreator, _>(
+            Box::new(pos),
+            16,
+            &mut maker,
+        )
+    }
+
+    // The operations in a storage log, with each leaf replaced by its height,
+    // for comparing logs which touch different but equally likely branches
+    fn log_shape(log: &[(&'static str, u64)]) -> Vec<(&'static str, u32)> {
+        log.iter().map(|(op, leaf)| (*op, leaf.height())).collect()
+    }
+
+    // A leaf assigner which assigns every block to the same leaf
+    struct FixedLeafAssigner(u64);
+
+    impl LeafAssigner<RngType> for FixedLeafAssigner {
+        fn assign_leaf(&mut self, _height: u32, _rng: &mut RngType) -> u64 {
+            self.0
+        }
+    }
+
+    // Test that with checkout reuse, consecutive accesses to the same leaf
+    // share a checkout, and that the results and the resulting ORAM are the
+    // same as with a checkout per access
+    #[test]
+    fn checkout_reuse_matches_independent_accesses() {
+        use crate::SubtreeLeafAssigner;
+        use rand_core::SeedableRng;
+
+        run_with_several_seeds(|rng| {
+            let mut orams = [new_recording_oram(rng.clone()), new_recording_oram(rng)];
+            orams[1].set_checkout_reuse(true);
+            let mut rng = RngType::from_seed([7u8; 32]);
+            for oram in orams.iter_mut() {
+                // Confine blocks to four leaves, so that leaves repeat often
+                let subtree = 1u64 << (oram.height - 2);
+                oram.set_leaf_assigner(Box::new(SubtreeLeafAssigner::new(subtree)));
+            }
+            for _ in 0..500 {
+                let key = rng.next_u64() % 8;
+                let val = a64_bytes(rng.next_u32() as u8);
+                let results: Vec<_> = orams.iter_mut().map(|oram| oram.write(key, &val)).collect();
+                assert_eq!(results[0], results[1]);
+                assert_eq!(orams[0].stash_meta, orams[1].stash_meta);
+            }
+
+            let num_checkouts: Vec<usize> = orams
+                .iter()
+                .map(|oram| {
+                    oram.storage
+                        .log
+                        .iter()
+                        .filter(|(op, _)| *op == "checkout")
+                        .count()
+                })
+                .collect();
+            assert_eq!(num_checkouts[0], 500);
+            assert!(num_checkouts[1] < 400, "{:?}", num_checkouts);
+
+            // The held branch is checked in before the tree is read
+            assert!(orams[1].branch.leaf != 0);
+            let digests: Vec<_> = orams.iter_mut().map(|oram| oram.digest()).collect();
+            assert_eq!(orams[1].branch.leaf, 0);
+            assert_eq!(digests[0], digests[1]);
+            for index in 1..(2u64 << orams[0].height) {
+                assert_eq!(
+                    orams[0].read_bucket_meta(index),
+                    orams[1].read_bucket_meta(index)
+                );
+            }
+        });
+    }
+
+    // Test that dropping an ORAM checks in the branch held by checkout reuse
+    #[test]
+    fn checkout_reuse_checks_in_on_drop() {
+        use rand_core::SeedableRng;
+        let mut oram = new_recording_oram(RngType::from_seed([7u8; 32]));
+        oram.set_checkout_reuse(true);
+        oram.write(1, &a64_bytes(1));
+        assert!(oram.branch.leaf != 0);
+        let checkins = oram.storage.checkins.clone();
+        let before = checkins.load(Ordering::SeqCst);
+        drop(oram);
+        assert_eq!(checkins.load(Ordering::SeqCst), before + 1);
+    }
+
+    // Test that checkout reuse can't be combined with sibling eviction
+    #[test]
+    #[should_panic(expected = "checkout reuse cannot be used with sibling eviction")]
+    fn checkout_reuse_rejects_sibling_eviction() {
+        use rand_core::SeedableRng;
+        let mut oram = new_recording_oram(RngType::from_seed([7u8; 32]));
+        oram.set_sibling_eviction_level(Some(1));
+        oram.set_checkout_reuse(true);
+    }
+
+    // Test that extra eviction rounds can't be combined with checkout reuse
+    #[test]
+    #[should_panic(expected = "extra eviction rounds cannot be used with checkout reuse")]
+    fn eviction_rounds_reject_checkout_reuse() {
+        use rand_core::SeedableRng;
+        let mut oram = new_recording_oram(RngType::from_seed([7u8; 32]));
+        oram.set_checkout_reuse(true);
+        oram.set_eviction_rounds(2);
+    }
+
+    // Build a checked-out branch to a given leaf, of a tree of height 3, holding
+    // blocks given as (branch index, leaf, block num). Branch index 0 is the
+    // leaf bucket. This need not respect the invariants.
+    fn branch_with_blocks(leaf: u64, blocks: &[(usize, u64, u64)]) -> BranchCheckout<U1024, U4> {
+        let mut branch = BranchCheckout::<U1024, U4> {
+            leaf,
+            data: vec![Default::default(); branch_len(3)],
+            meta: vec![Default::default(); branch_len(3)],
+            ..Default::default()
+        };
+        for (idx, block_leaf, block_num) in blocks {
+            let mut meta = make_meta(*block_leaf, *block_num);
+            details::ct_insert::<U1024>(
+                1.into(),
+                &a64_bytes(*block_num as u8),
+                &mut meta,
+                branch.data[*idx].as_mut_aligned_chunks(),
+                branch.meta[*idx].as_mut_aligned_chunks(),
+            );
+            assert!(bool::from(meta_is_vacant(&meta)), "bucket is full");
+        }
+        branch
+    }
+
+    // Count the non-vacant slots of a checked-out branch
+    fn branch_occupancy(branch: &BranchCheckout<U1024, U4>) -> u64 {
+        branch
+            .meta
+            .iter()
+            .map(|bucket_meta| details::ct_count_occupied(bucket_meta.as_aligned_chunks()))
+            .sum()
+    }
+
+    // Test that a transfer between branches happens under true, not under false,
+    // and not into an occupied slot, and that it conserves the number of blocks
+    #[test]
+    fn transfer_between_branches() {
+        let mut src = branch_with_blocks(9, &[(0, 9, 1), (2, 10, 2)]);
+        let mut dst = branch_with_blocks(10, &[(0, 10, 3)]);
+        let total = branch_occupancy(&src) + branch_occupancy(&dst);
+        assert_eq!(total, 3);
+
+        // Block 2 belongs to leaf 10, so it may move down to bucket 5
+        let moved = BranchCheckout::ct_transfer(0.into(), &mut src, (2, 0), &mut dst, (1, 1));
+        assert!(!bool::from(moved));
+        assert_eq!(branch_occupancy(&src), 2);
+        assert_eq!(branch_occupancy(&dst), 1);
+
+        let moved = BranchCheckout::ct_transfer(1.into(), &mut src, (2, 0), &mut dst, (1, 1));
+        assert!(bool::from(moved));
+        assert_eq!(branch_occupancy(&src) + branch_occupancy(&dst), total);
+        let dst_meta: &[A8Bytes<MetaSize>] = dst.meta[1].as_aligned_chunks();
+        let dst_data: &[A64Bytes<U1024>] = dst.data[1].as_aligned_chunks();
+        assert_eq!(dst_meta[1], make_meta(10, 2));
+        assert_eq!(dst_data[1], a64_bytes(2));
+        let src_meta: &[A8Bytes<MetaSize>] = src.meta[2].as_aligned_chunks();
+        assert_eq!(src_meta[0], A8Bytes::<MetaSize>::default());
+        src.check_invariants(&[]);
+        dst.check_invariants(&[]);
+
+        // Moving into an occupied slot, or out of a vacant one, does nothing
+        let moved = BranchCheckout::ct_transfer(1.into(), &mut src, (0, 0), &mut dst, (0, 0));
+        assert!(!bool::from(moved));
+        let moved = BranchCheckout::ct_transfer(1.into(), &mut src, (2, 0), &mut dst, (0, 1));
+        assert!(!bool::from(moved));
+        assert_eq!(branch_occupancy(&src) + branch_occupancy(&dst), total);
+        let dst_meta: &[A8Bytes<MetaSize>] = dst.meta[0].as_aligned_chunks();
+        assert_eq!(dst_meta[0], make_meta(10, 3));
+    }
+
+    // Test that transferring out of a slot emptied by a normal access does
+    // nothing
+    #[test]
+    fn transfer_from_removed_slot() {
+        let mut src = branch_with_blocks(9, &[(0, 9, 1), (2, 10, 2)]);
+        let mut dst = branch_with_blocks(10, &[(0, 10, 3)]);
+
+        let mut data = A64Bytes::<U1024>::default();
+        let mut meta = A8Bytes::<MetaSize>::default();
+        src.ct_find_and_remove(1.into(), &2, &mut data, &mut meta);
+        assert_eq!(meta, make_meta(10, 2));
+        assert_eq!(branch_occupancy(&src), 1);
+
+        let moved = BranchCheckout::ct_transfer(1.into(), &mut src, (2, 0), &mut dst, (1, 1));
+        assert!(!bool::from(moved));
+        assert_eq!(branch_occupancy(&src), 1);
+        assert_eq!(branch_occupancy(&dst), 1);
+        let dst_meta: &[A8Bytes<MetaSize>] = dst.meta[1].as_aligned_chunks();
+        assert_eq!(dst_meta[1], A8Bytes::<MetaSize>::default());
+        src.check_invariants(&[]);
+        dst.check_invariants(&[]);
+    }
+
+    // Test that a well-formed branch passes the invariant checks
+    #[test]
+    fn check_invariants_accepts_valid_branch() {
+        let branch = branch_with_blocks(9, &[(0, 9, 1), (1, 8, 2), (3, 15, 3), (3, 12, 4)]);
+        branch.check_invariants(&[make_meta(10, 5), Default::default()]);
+    }
+
+    // A block in a bucket which is not an ancestor of its leaf
+    #[test]
+    #[should_panic(expected = "is not on the branch of its leaf")]
+    fn check_invariants_catches_misplaced_block() {
+        let branch = branch_with_blocks(9, &[(0, 9, 1), (1, 10, 2)]);
+        branch.check_invariants(&[]);
+    }
+
+    // The same block num twice in the branch
+    #[test]
+    #[should_panic(expected = "appears more than once")]
+    fn check_invariants_catches_duplicate_in_branch() {
+        let branch = branch_with_blocks(9, &[(0, 9, 1), (2, 8, 1)]);
+        branch.check_invariants(&[]);
+    }
+
+    // The same block num in the branch and in the stash
+    #[test]
+    #[should_panic(expected = "appears more than once")]
+    fn check_invariants_catches_duplicate_in_stash() {
+        let branch = branch_with_blocks(9, &[(3, 12, 7)]);
+        branch.check_invariants(&[make_meta(11, 7)]);
+    }
+
+    // A block whose leaf is not at the height of the tree
+    #[test]
+    #[should_panic(expected = "has an invalid leaf")]
+    fn check_invariants_catches_invalid_leaf() {
+        let branch = branch_with_blocks(9, &[(3, 3, 7)]);
+        branch.check_invariants(&[]);
+    }
+
+    // Collect the (bucket index, leaf, block num) of every block in the tree,
+    // using read_bucket_meta
+    fn live_blocks_by_bucket<O: ORAM<U1024>>(
+        oram: &mut O,
+        height: u32,
+        read: impl Fn(&mut O, u64) -> A8Bytes<Prod<U4, MetaSize>>,
+    ) -> Vec<(u64, u64, u64)> {
+        let mut result = Vec::new();
+        for index in 1u64..(2u64 << height) {
+            let bucket_meta = read(oram, index);
+            let bucket_meta: &[A8Bytes<MetaSize>] = bucket_meta.as_aligned_chunks();
+            for meta in bucket_meta {
+                if !bool::from(meta_is_vacant(meta)) {
+                    result.push((index, *meta_leaf_num(meta), meta_block_num(meta)));
+                }
+            }
+        }
+        result
+    }
+
+    // Test that the raw bucket metadata shows a block on the branch of its leaf,
+    // both for storage that reads it directly, and for the default implementation
+    #[test]
+    fn read_bucket_meta_decodes() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut oram = new_test_oram(&mut maker);
+            let height = oram.height;
+            oram.write(5, &a64_bytes(5));
+            let found = live_blocks_by_bucket(&mut oram, height, |o, i| o.read_bucket_meta(i));
+            assert_eq!(found.len(), 1);
+            let (index, leaf, block_num) = found[0];
+            assert_eq!(block_num, 5);
+            assert!(leaf.parents().any(|p| p == index));
+            assert_eq!(oram.read(5), a64_bytes(5));
+
+            let mut oram = new_recording_oram(maker());
+            let height = oram.height;
+            oram.write(6, &a64_bytes(6));
+            let found = live_blocks_by_bucket(&mut oram, height, |o, i| o.read_bucket_meta(i));
+            assert_eq!(found.len(), 1);
+            let (index, leaf, block_num) = found[0];
+            assert_eq!(block_num, 6);
+            assert!(leaf.parents().any(|p| p == index));
+            assert_eq!(oram.read(6), a64_bytes(6));
+        });
+    }
+
+    // Run two operations on two fresh ORAMs built from the same seed, and
+    // assert that they touch storage in exactly the same sequence.
+    //
+    // The position map places each key at a random leaf on its first access,
+    // so every key starts out equally likely to be anywhere, and with the same
+    // seed, an operation which doesn't leak gives an identical sequence.
+    fn assert_access_patterns_equal(
+        rng: RngType,
+        op_a: impl FnOnce(&mut RecordingORAM),
+        op_b: impl FnOnce(&mut RecordingORAM),
+    ) {
+        let mut oram_a = new_recording_oram(rng.clone());
+        let mut oram_b = new_recording_oram(rng);
+        op_a(&mut oram_a);
+        op_b(&mut oram_b);
+        assert!(!oram_a.storage.log.is_empty(), "nothing was recorded");
+        assert_eq!(oram_a.storage.log, oram_b.storage.log);
+    }
+
+    // Test that reads of different keys touch storage in the same way
+    #[test]
+    fn access_pattern_read_vs_read() {
+        run_with_several_seeds(|rng| {
+            assert_access_patterns_equal(
+                rng,
+                |oram| {
+                    oram.read(3);
+                },
+                |oram| {
+                    oram.read(700);
+                },
+            );
+        });
+    }
+
+    // Test that a read and a write of the same key touch storage in the same way,
+    // so the access pattern doesn't reveal the kind of operation, or the value
+    #[test]
+    fn access_pattern_read_vs_write() {
+        run_with_several_seeds(|rng| {
+            assert_access_patterns_equal(
+                rng,
+                |oram| {
+                    for key in 0..32 {
+                        oram.read(key);
+                    }
+                },
+                |oram| {
+                    for key in 0..32 {
+                        oram.write(key, &a64_bytes(key as u8));
+                    }
+                },
+            );
+        });
+    }
+
+    // Test that access_pair touches storage in the same shape whether the keys
+    // are distinct or equal, and that it swaps correctly
+    #[test]
+    fn access_pattern_pair_overlapping() {
+        run_with_several_seeds(|rng| {
+            let mut distinct = new_recording_oram(rng.clone());
+            let mut equal = new_recording_oram(rng);
+            for oram in [&mut distinct, &mut equal].iter_mut() {
+                oram.write(3, &a64_bytes(3));
+                oram.write(700, &a64_bytes(7));
+                oram.storage.log.clear();
+            }
+
+            let swap = |a: &mut A64Bytes<U1024>, b: &mut A64Bytes<U1024>| {
+                cswap(1.into(), a, b);
+            };
+            distinct.access_pair(3, 700, swap);
+            equal.access_pair(3, 3, swap);
+            assert_eq!(
+                log_shape(&distinct.storage.log),
+                log_shape(&equal.storage.log)
+            );
+
+            assert_eq!(distinct.read(3), a64_bytes(7));
+            assert_eq!(distinct.read(700), a64_bytes(3));
+            assert_eq!(equal.read(3), a64_bytes(3));
+            assert_eq!(equal.read(700), a64_bytes(7));
+        });
+    }
+
+    // Test that the key scrambler is a permutation, for odd and even numbers of
+    // bits, and that invert undoes it
+    #[test]
+    fn key_scrambler_is_permutation() {
+        run_with_several_seeds(|mut rng| {
+            for bits in 0..12 {
+                let key_scrambler = KeyScrambler::new(bits, &mut rng);
+                let mut seen = vec![false; 1 << bits];
+                for value in 0..(1u64 << bits) {
+                    let permuted = key_scrambler.permute(value);
+                    assert!(permuted < 1 << bits);
+                    assert!(!seen[permuted as usize], "not a permutation");
+                    seen[permuted as usize] = true;
+                    assert_eq!(key_scrambler.invert(permuted), value);
+                }
+            }
+        });
+    }
+
+    // Test that with key scrambling, reads are still correct, but the block nums
+    // stored in the ORAM are not the keys
+    #[test]
+    fn key_scrambling() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng = maker();
+            let mut plain = new_test_oram(&mut maker);
+            let mut scrambled = new_test_oram(&mut maker);
+            scrambled.enable_key_scrambling();
+
+            for key in 0..8 {
+                plain.write(key, &a64_bytes(key as u8 + 1));
+                scrambled.write(key, &a64_bytes(key as u8 + 1));
+            }
+            let stored_block_nums = |oram: &mut TestORAM| -> Vec<u64> {
+                let height = oram.height;
+                let mut result: Vec<u64> =
+                    live_blocks_by_bucket(oram, height, |o, i| o.read_bucket_meta(i))
+                        .into_iter()
+                        .map(|(_, _, block_num)| block_num)
+                        .chain(stash_block_nums(oram))
+                        .collect();
+                result.sort_unstable();
+                result
+            };
+            assert_eq!(stored_block_nums(&mut plain), (0..8).collect::<Vec<_>>());
+            let scrambled_block_nums = stored_block_nums(&mut scrambled);
+            assert_eq!(scrambled_block_nums.len(), 8);
+            assert_ne!(scrambled_block_nums, (0..8).collect::<Vec<_>>());
+
+            // The digest and deletion see the original keys
+            assert_eq!(plain.digest(), scrambled.digest());
+            scrambled.delete_where(|block_num, _| block_num == 3);
+            assert_eq!(scrambled.read(3), a64_bytes(0));
+            for key in (0..8).filter(|key| *key != 3) {
+                assert_eq!(scrambled.read(key), a64_bytes(key as u8 + 1));
+            }
+            fill_and_exercise(&mut scrambled, 1_000, &mut rng);
+        });
+    }
+
+    // Test that a dummy access touches storage in the same shape as a real
+    // access, and doesn't change any value
+    #[test]
+    fn dummy_access_matches_real_access() {
+        run_with_several_seeds(|rng| {
+            let mut real = new_recording_oram(rng.clone());
+            let mut dummy = new_recording_oram(rng);
+            for key in 0..16 {
+                real.write(key, &a64_bytes(key as u8 + 1));
+                dummy.write(key, &a64_bytes(key as u8 + 1));
+            }
+            dummy.set_sibling_eviction_level(Some(2));
+            real.set_sibling_eviction_level(Some(2));
+            real.storage.log.clear();
+            dummy.storage.log.clear();
+
+            real.read(5);
+            dummy.dummy_access();
+            assert_eq!(log_shape(&real.storage.log), log_shape(&dummy.storage.log));
+
+            for key in 0..16 {
+                assert_eq!(dummy.read(key), a64_bytes(key as u8 + 1));
+            }
+
+            // Dummy accesses at keys which were never written leave them vacant
+            for _ in 0..256 {
+                dummy.dummy_access();
+            }
+            for key in 16..dummy.len() {
+                let existed = dummy.upsert(key, &a64_bytes(1), |_| {}).unwrap();
+                assert!(!bool::from(existed), "key {} exists", key);
+            }
+        });
+    }
+
+    // A storage which can be made to drop writes, by checking in each branch
+    // as it was checked out
+    struct DroppingStorage {
+        inner: HeapORAMStorage<U4096, U64>,
+        drop_writes: bool,
+        stale_data: Vec<A64Bytes<U4096>>,
+        stale_meta: Vec<A8Bytes<U64>>,
+    }
+
+    impl ORAMStorage<U4096, U64> for DroppingStorage {
+        fn len(&self) -> u64 {
+            self.inner.len()
+        }
+        fn checkout(
+            &mut self,
+            leaf_index: u64,
+            dest: &mut [A64Bytes<U4096>],
+            dest_meta: &mut [A8Bytes<U64>],
+        ) {
+            self.inner.checkout(leaf_index, dest, dest_meta);
+            self.stale_data = dest.to_vec();
+            self.stale_meta = dest_meta.to_vec();
+        }
+        fn checkin(
+            &mut self,
+            leaf_index: u64,
+            src: &mut [A64Bytes<U4096>],
+            src_meta: &mut [A8Bytes<U64>],
+        ) {
+            if self.drop_writes {
+                self.inner
+                    .checkin(leaf_index, &mut self.stale_data, &mut self.stale_meta)
+            } else {
+                self.inner.checkin(leaf_index, src, src_meta)
+            }
+        }
+    }
+
+    struct DroppingStorageCreator;
+
+    impl ORAMStorageCreator<U4096, U64> for DroppingStorageCreator {
+        type Output = DroppingStorage;
+        type Error = <HeapORAMStorageCreator as ORAMStorageCreator<U4096, U64>>::Error;
+
+        fn create<R: RngCore + CryptoRng>(
+            size: u64,
+            rng: &mut R,
+        ) -> Result<Self::Output, Self::Error> {
+            Ok(DroppingStorage {
+                inner: HeapORAMStorageCreator::create(size, rng)?,
+                drop_writes: false,
+                stale_data: Vec::new(),
+                stale_meta: Vec::new(),
+            })
+        }
+    }
+
+    type DroppingORAM = PathORAM<U1024, U4, DroppingStorage, RngType>;
+
+    // Make an ORAM on storage which can drop writes, write some keys, then
+    // start dropping writes. Returns a key whose block is in the tree, so that
+    // accessing it changes the branch which is checked out.
+    fn new_dropping_oram(rng: RngType, verify: bool) -> (DroppingORAM, u64) {
+        let mut maker = rng_maker(rng);
+        let pos = MockPositionMap {
+            data: vec![0; 1024],
+            height: DroppingORAM::height_for_size(1024),
+            rng: maker(),
+            num_writes: Default::default(),
+        };
+        let mut oram = DroppingORAM::new_with_position_map::<DroppingStorageCreator, _>(
+            Box::new(pos),
+            16,
+            &mut maker,
+        );
+        if verify {
+            oram.enable_write_verification();
+        }
+        for key in 0..100 {
+            assert_eq!(oram.try_access(key, |val| *val = a64_bytes(1)), Ok(()));
+        }
+        let key = (0..100)
+            .find(|key| {
+                !oram
+                    .stash_meta
+                    .iter()
+                    .any(|meta| !bool::from(meta_is_vacant(meta)) && meta_block_num(meta) == *key)
+            })
+            .expect("every block is in the stash");
+        oram.storage.drop_writes = true;
+        (oram, key)
+    }
+
+    // Test that with write verification, storage which drops writes is caught
+    #[test]
+    fn write_verification_catches_dropped_writes() {
+        run_with_several_seeds(|rng| {
+            let (mut oram, key) = new_dropping_oram(rng, true);
+            assert_eq!(
+                oram.try_access(key, |val| *val = a64_bytes(2)),
+                Err(ORAMError::WriteVerificationFailed)
+            );
+        });
+    }
+
+    // Test that without write verification, dropped writes go unnoticed
+    #[test]
+    fn dropped_writes_unnoticed_without_verification() {
+        run_with_several_seeds(|rng| {
+            let (mut oram, key) = new_dropping_oram(rng, false);
+            assert_eq!(oram.try_access(key, |val| *val = a64_bytes(2)), Ok(()));
+        });
+    }
+
+    // Test that the bucket iterators visit the branch in the documented orders,
+    // the root to leaf order being the one that format_branch uses
+    #[test]
+    fn branch_bucket_iterators() {
+        let branch = branch_with_blocks(9, &[(0, 9, 1), (1, 8, 2), (3, 15, 3)]);
+        let root_to_leaf: Vec<(u32, u64)> = branch
+            .buckets_root_to_leaf()
+            .map(|(level, data, meta)| {
+                let meta: &[A8Bytes<MetaSize>] = meta.as_aligned_chunks();
+                let data: &[A64Bytes<U1024>] = data.as_aligned_chunks();
+                assert_eq!(data[0], a64_bytes(meta_block_num(&meta[0]) as u8));
+                (level, meta_block_num(&meta[0]))
+            })
+            .collect();
+        assert_eq!(root_to_leaf, vec![(0, 3), (1, 0), (2, 2), (3, 1)]);
+
+        let leaf_to_root: Vec<(u32, u64)> = branch
+            .buckets_leaf_to_root()
+            .map(|(level, _, meta)| {
+                let meta: &[A8Bytes<MetaSize>] = meta.as_aligned_chunks();
+                (level, meta_block_num(&meta[0]))
+            })
+            .collect();
+        assert_eq!(leaf_to_root, vec![(3, 1), (2, 2), (1, 0), (0, 3)]);
+    }
 }
@@ -0,0 +1,80 @@
+//! A keyed pseudorandom permutation of the block numbers of an ORAM, so that
+//! the index used in the position map, and the block number stored in the
+//! metadata, are not correlated with the key which the caller asked for.
+//!
+//! The permutation is a Feistel network over the bits of the block number,
+//! with a keyed SipHash as the round function. The halves may differ in size
+//! by one bit, when the number of bits is odd, and each round swaps which half
+//! is which, so an even number of rounds gets back to the original split.
+//! Every round does the same work regardless of the input, so this is
+//! constant time.
+
+use core::hash::Hasher;
+use rand_core::{CryptoRng, RngCore};
+use siphasher::sip::SipHasher13;
+
+/// The number of Feistel rounds. This must be even.
+const ROUNDS: usize = 4;
+
+/// A keyed permutation of the integers in 0..2^bits
+#[derive(Clone)]
+pub struct KeyScrambler {
+    keys: [(u64, u64); ROUNDS],
+    bits: u32,
+}
+
+impl KeyScrambler {
+    /// Make a new permutation of 0..2^bits, with fresh keys
+    pub fn new<R: RngCore + CryptoRng>(bits: u32, rng: &mut R) -> Self {
+        assert!(bits < 64, "too many bits");
+        let mut keys = [(0u64, 0u64); ROUNDS];
+        for key in keys.iter_mut() {
+            *key = (rng.next_u64(), rng.next_u64());
+        }
+        Self { keys, bits }
+    }
+
+    /// Apply the permutation
+    pub fn permute(&self, value: u64) -> u64 {
+        debug_assert!(value >> self.bits == 0, "value out of range");
+        let mut b_bits = self.bits / 2;
+        let mut a_bits = self.bits - b_bits;
+        let mut a = value >> b_bits;
+        let mut b = value & mask(b_bits);
+        for round in 0..ROUNDS {
+            let new_b = a ^ (self.round_function(round, b) & mask(a_bits));
+            a = b;
+            b = new_b;
+            core::mem::swap(&mut a_bits, &mut b_bits);
+        }
+        (a << b_bits) | b
+    }
+
+    /// Apply the inverse of the permutation
+    pub fn invert(&self, value: u64) -> u64 {
+        debug_assert!(value >> self.bits == 0, "value out of range");
+        let mut b_bits = self.bits / 2;
+        let mut a_bits = self.bits - b_bits;
+        let mut a = value >> b_bits;
+        let mut b = value & mask(b_bits);
+        for round in (0..ROUNDS).rev() {
+            let prev_a = b ^ (self.round_function(round, a) & mask(b_bits));
+            b = a;
+            a = prev_a;
+            core::mem::swap(&mut a_bits, &mut b_bits);
+        }
+        (a << b_bits) | b
+    }
+
+    fn round_function(&self, round: usize, value: u64) -> u64 {
+        let (k0, k1) = self.keys[round];
+        let mut hasher = SipHasher13::new_with_keys(k0, k1);
+        hasher.write_u64(value);
+        hasher.finish()
+    }
+}
+
+/// The low `bits` bits set
+fn mask(bits: u32) -> u64 {
+    (1u64 << bits) - 1
+}
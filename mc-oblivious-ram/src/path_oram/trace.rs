@@ -0,0 +1,85 @@
+//! Replaying a recorded trace of accesses, for offline analysis of the stash.
+//!
+//! A trace records, for each access, the key, the leaf whose branch was
+//! checked out, and the new leaf which the block was assigned. Replaying it
+//! gives exactly the eviction decisions of the recorded run, whatever the
+//! seeds, since those are the only random choices an access makes.
+
+use crate::LeafAssigner;
+use alloc::{vec, vec::Vec};
+use mc_oblivious_traits::PositionMap;
+use rand_core::{CryptoRng, RngCore};
+
+/// One access of a recorded trace
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TraceAccess {
+    /// The key which was accessed
+    pub key: u64,
+    /// The leaf of the branch which was checked out
+    pub leaf: u64,
+    /// The leaf which the block was assigned by the access
+    pub new_leaf: u64,
+}
+
+/// A position map which returns the recorded leaves of a trace, in order,
+/// and checks that they are consistent with the recorded new leaves
+pub struct ReplayPositionMap {
+    data: Vec<u64>,
+    leaves: Vec<u64>,
+    next: usize,
+}
+
+impl ReplayPositionMap {
+    /// Make a position map of a given size, which replays the checked-out
+    /// leaves of a trace
+    pub fn new(size: u64, trace: &[TraceAccess]) -> Self {
+        Self {
+            data: vec![0; size as usize],
+            leaves: trace.iter().map(|access| access.leaf).collect(),
+            next: 0,
+        }
+    }
+}
+
+impl PositionMap for ReplayPositionMap {
+    fn len(&self) -> u64 {
+        self.data.len() as u64
+    }
+    fn write(&mut self, key: &u64, new_val: &u64) -> u64 {
+        let old_val = core::mem::replace(&mut self.data[*key as usize], *new_val);
+        let leaf = self.leaves[self.next];
+        self.next += 1;
+        assert!(
+            old_val == 0 || old_val == leaf,
+            "trace checks out leaf {} for key {}, which is at leaf {}",
+            leaf,
+            key,
+            old_val
+        );
+        leaf
+    }
+}
+
+/// A leaf assigner which returns the recorded new leaves of a trace, in order
+pub struct ReplayLeafAssigner {
+    new_leaves: Vec<u64>,
+    next: usize,
+}
+
+impl ReplayLeafAssigner {
+    /// Make a leaf assigner which replays the new leaves of a trace
+    pub fn new(trace: &[TraceAccess]) -> Self {
+        Self {
+            new_leaves: trace.iter().map(|access| access.new_leaf).collect(),
+            next: 0,
+        }
+    }
+}
+
+impl<R: RngCore + CryptoRng> LeafAssigner<R> for ReplayLeafAssigner {
+    fn assign_leaf(&mut self, _height: u32, _rng: &mut R) -> u64 {
+        let leaf = self.new_leaves[self.next];
+        self.next += 1;
+        leaf
+    }
+}
@@ -41,6 +41,27 @@ impl<R: RngCore + CryptoRng> TrivialPositionMap<R> {
             rng: rng_maker(),
         }
     }
+
+    /// Get the leaf currently assigned to a key, or 0 if it was never written.
+    /// This scans the whole map, and does not change it.
+    pub fn get_position(&self, key: u64) -> u64 {
+        debug_assert!(key < self.data.len() as u64, "key was out of bounds");
+        let key = key as u32;
+        let mut result = 0u32;
+        for idx in 0..self.data.len() {
+            result.cmov((idx as u32).ct_eq(&key), &self.data[idx]);
+        }
+        result as u64
+    }
+
+    /// Get the leaf assigned to every key, in order of key, with 0 for keys
+    /// which were never written, e.g. for snapshots and debugging.
+    ///
+    /// This is a maintenance operation and is NOT oblivious: the whole map is
+    /// revealed to the caller.
+    pub fn export_positions(&self) -> Vec<u64> {
+        self.data.iter().map(|leaf| *leaf as u64).collect()
+    }
 }
 
 impl<R: RngCore + CryptoRng> PositionMap for TrivialPositionMap<R> {
@@ -185,3 +206,35 @@ impl<
         }
     }
 }
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use mc_oblivious_traits::rng_maker;
+    use test_helper::{run_with_several_seeds, RngType};
+
+    // Test that exported positions match the positions of each key
+    #[test]
+    fn export_positions_matches_get_position() {
+        run_with_several_seeds(|rng| {
+            let mut maker = rng_maker(rng);
+            let mut rng: RngType = maker();
+            let mut pos_map = TrivialPositionMap::<RngType>::new(100, 10, &mut maker);
+            assert_eq!(pos_map.export_positions(), vec![0u64; 100]);
+
+            for _ in 0..300 {
+                let key = rng.next_u64() % 80;
+                let leaf = 1u64.random_child_at_height(10, &mut rng);
+                pos_map.write(&key, &leaf);
+                assert_eq!(pos_map.get_position(key), leaf);
+            }
+
+            let positions = pos_map.export_positions();
+            assert_eq!(positions.len(), 100);
+            for (key, leaf) in positions.iter().enumerate() {
+                assert_eq!(*leaf, pos_map.get_position(key as u64));
+            }
+            assert!(positions[80..].iter().all(|leaf| *leaf == 0));
+        });
+    }
+}
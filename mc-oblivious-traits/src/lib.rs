@@ -13,7 +13,8 @@
 use core::fmt::{Debug, Display};
 
 extern crate alloc;
-use alloc::vec::Vec;
+use alloc::{vec, vec::Vec};
+use balanced_tree_index::TreeIndex;
 
 // Re-export some traits we depend on, so that downstream can ensure that they
 // have the same version as us.
@@ -21,7 +22,9 @@ pub use aligned_cmov::{
     cswap, subtle, typenum, A64Bytes, A8Bytes, ArrayLength, CMov, GenericArray,
 };
 pub use rand_core::{CryptoRng, RngCore};
-use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
+use subtle::{
+    Choice, ConditionallySelectable, ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess,
+};
 
 mod naive_storage;
 pub use naive_storage::{HeapORAMStorage, HeapORAMStorageCreator};
@@ -29,6 +32,14 @@ pub use naive_storage::{HeapORAMStorage, HeapORAMStorageCreator};
 mod linear_scanning;
 pub use linear_scanning::LinearScanningORAM;
 
+mod snapshot;
+pub use snapshot::{states_equal, ORAMSnapshot};
+
+#[cfg(feature = "serde")]
+mod oblivious_store;
+#[cfg(feature = "serde")]
+pub use oblivious_store::{ObliviousStore, StoreError};
+
 mod creators;
 pub use creators::*;
 
@@ -95,6 +106,70 @@ pub trait ORAMStorage<BlockSize: ArrayLength<u8>, MetaSize: ArrayLength<u8>> {
         src: &mut [A64Bytes<BlockSize>],
         src_meta: &mut [A8Bytes<MetaSize>],
     );
+
+    /// Touch all of the storage sequentially, so that later accesses don't
+    /// incur page faults or other first-access costs, and have more predictable
+    /// latency. This is non-secret maintenance, and does not change the contents.
+    ///
+    /// The default implementation does nothing.
+    fn prewarm(&mut self) {}
+
+    /// Read the raw metadata of the bucket at a particular index in the tree,
+    /// for tooling which inspects the layout of the tree. This is non-secret
+    /// maintenance, and the index is revealed.
+    ///
+    /// Requirements:
+    /// * 0 < index < len
+    /// * It is illegal to read while there is an existing checkout.
+    ///
+    /// The default implementation checks out, and checks back in unchanged, the
+    /// leftmost branch passing through the bucket.
+    fn read_bucket_meta(&mut self, index: u64) -> A8Bytes<MetaSize> {
+        debug_assert!(index != 0 && index < self.len(), "index out of bounds");
+        let mut leaf = index;
+        while leaf * 2 < self.len() {
+            leaf *= 2;
+        }
+        let branch_len = leaf.height() as usize + 1;
+        let mut data: Vec<A64Bytes<BlockSize>> = vec![Default::default(); branch_len];
+        let mut meta: Vec<A8Bytes<MetaSize>> = vec![Default::default(); branch_len];
+        self.checkout(leaf, &mut data, &mut meta);
+        let result = meta[(leaf.height() - index.height()) as usize].clone();
+        self.checkin(leaf, &mut data, &mut meta);
+        result
+    }
+
+    /// Call a function on the index, data and metadata of every bucket in the
+    /// tree, once each, keeping any changes it makes. This is non-secret
+    /// maintenance: every bucket is visited, in a fixed order, whatever the
+    /// function does.
+    ///
+    /// Requirements:
+    /// * It is illegal to call this while there is an existing checkout.
+    ///
+    /// The default implementation checks out every branch in turn, and visits
+    /// each bucket from the leftmost branch passing through it. This transfers
+    /// height + 1 buckets per leaf, so storage which can address buckets
+    /// directly should override it.
+    fn for_each_bucket(
+        &mut self,
+        f: &mut dyn FnMut(u64, &mut A64Bytes<BlockSize>, &mut A8Bytes<MetaSize>),
+    ) {
+        let first_leaf = self.len() / 2;
+        let branch_len = first_leaf.height() as usize + 1;
+        let mut data: Vec<A64Bytes<BlockSize>> = vec![Default::default(); branch_len];
+        let mut meta: Vec<A8Bytes<MetaSize>> = vec![Default::default(); branch_len];
+        for leaf in first_leaf..self.len() {
+            self.checkout(leaf, &mut data, &mut meta);
+            for idx in 0..branch_len {
+                // Visit each bucket only once, from the first leaf beneath it
+                if leaf & ((1u64 << idx) - 1) == 0 {
+                    f(leaf >> idx, &mut data[idx], &mut meta[idx]);
+                }
+            }
+            self.checkin(leaf, &mut data, &mut meta);
+        }
+    }
 }
 
 /// An Oblivious RAM -- that is, an array like [A8Bytes<ValueSize>; N]
@@ -132,6 +207,35 @@ pub trait ORAM<ValueSize: ArrayLength<u8>> {
         self.access(index, |val| val.clone())
     }
 
+    /// High-level helper -- when you only need to look at a value, without
+    /// copying it out or changing it, this gives the lambda a shared reference.
+    /// The access is otherwise the same as any other, so implementations still
+    /// write the (unchanged) value back, e.g. for leaf reassignment.
+    #[inline]
+    fn access_ref<T, F: FnOnce(&A64Bytes<ValueSize>) -> T>(&mut self, index: u64, func: F) -> T {
+        self.access(index, |val| func(val))
+    }
+
+    /// High-level helper -- when you need to update two values together, e.g. to
+    /// conditionally swap them, this gives the lambda both of them at once.
+    ///
+    /// This is always three accesses: to key_a, key_b, and key_a again, to write
+    /// back the first value. So the access pattern is the same whether or not
+    /// the keys are equal, or their paths overlap.
+    /// If the keys are equal, both arguments start as the same value, and the
+    /// first argument is what is stored afterwards.
+    #[inline]
+    fn access_pair<F: FnOnce(&mut A64Bytes<ValueSize>, &mut A64Bytes<ValueSize>)>(
+        &mut self,
+        key_a: u64,
+        key_b: u64,
+        func: F,
+    ) {
+        let mut val_a = self.read(key_a);
+        self.access(key_b, |val_b| func(&mut val_a, val_b));
+        self.write(key_a, &val_a);
+    }
+
     /// High-level helper -- when you need to write a value and want the previous value,
     /// but you don't need to see the previous value when deciding what to write,
     /// this is simpler than using `access`.
@@ -144,6 +248,252 @@ pub trait ORAM<ValueSize: ArrayLength<u8>> {
             retval
         })
     }
+
+    /// High-level helper -- when a value is made of several fields, and you only
+    /// need to read one of them, this returns the LEN bytes starting at OFFSET.
+    /// The access itself is the same as any other, only the copying out is
+    /// restricted to the field. OFFSET and LEN are public, compile-time values.
+    ///
+    /// Panics if the field does not fit in the value.
+    #[inline]
+    fn read_field<const OFFSET: usize, const LEN: usize>(&mut self, index: u64) -> [u8; LEN] {
+        assert!(OFFSET + LEN <= ValueSize::USIZE, "field out of bounds");
+        self.access_ref(index, |val| {
+            let mut retval = [0u8; LEN];
+            retval.copy_from_slice(&val[OFFSET..OFFSET + LEN]);
+            retval
+        })
+    }
+
+    /// High-level helper -- when a value is made of several fields, and you only
+    /// need to write one of them, this overwrites the LEN bytes starting at OFFSET,
+    /// and returns their previous contents. The rest of the value is unchanged.
+    /// OFFSET and LEN are public, compile-time values.
+    ///
+    /// Panics if the field does not fit in the value.
+    #[inline]
+    fn write_field<const OFFSET: usize, const LEN: usize>(
+        &mut self,
+        index: u64,
+        new_field: &[u8; LEN],
+    ) -> [u8; LEN] {
+        assert!(OFFSET + LEN <= ValueSize::USIZE, "field out of bounds");
+        self.access(index, |val| {
+            let mut retval = [0u8; LEN];
+            retval.copy_from_slice(&val[OFFSET..OFFSET + LEN]);
+            val[OFFSET..OFFSET + LEN].copy_from_slice(new_field);
+            retval
+        })
+    }
+
+    /// High-level helper -- for rate limiting, increment a counter in a value,
+    /// and test whether it now exceeds a limit, in one access. The counter is
+    /// the little-endian u64 at COUNTER_OFFSET, and it saturates rather than
+    /// wrapping.
+    ///
+    /// The comparison is constant-time, so the access pattern reveals neither
+    /// the counter nor the result.
+    ///
+    /// Panics if the counter field does not fit in the value.
+    #[inline]
+    fn increment_and_check<const COUNTER_OFFSET: usize>(
+        &mut self,
+        index: u64,
+        limit: u64,
+    ) -> Choice {
+        assert!(
+            COUNTER_OFFSET + 8 <= ValueSize::USIZE,
+            "counter field out of bounds"
+        );
+        self.access(index, |val| {
+            let mut counter_bytes = [0u8; 8];
+            counter_bytes.copy_from_slice(&val[COUNTER_OFFSET..COUNTER_OFFSET + 8]);
+            let counter = u64::from_le_bytes(counter_bytes).saturating_add(1);
+            val[COUNTER_OFFSET..COUNTER_OFFSET + 8].copy_from_slice(&counter.to_le_bytes());
+            counter.ct_gt(&limit)
+        })
+    }
+
+    /// High-level helper -- for keeping a few recent versions of a key, write a
+    /// new version of LEN bytes into a ring of VERSIONS versions in the value,
+    /// overwriting the oldest one, and return it.
+    ///
+    /// The value starts with the little-endian u64 ring position of the next
+    /// version to write, followed by the VERSIONS versions of LEN bytes each.
+    /// Every version is visited, so the access pattern doesn't reveal the
+    /// ring position. See `read_version`.
+    ///
+    /// Panics if the ring does not fit in the value.
+    fn write_version<const LEN: usize, const VERSIONS: usize>(
+        &mut self,
+        index: u64,
+        new_version: &[u8; LEN],
+    ) -> [u8; LEN] {
+        assert!(VERSIONS > 0, "there must be at least one version");
+        assert!(
+            8 + VERSIONS * LEN <= ValueSize::USIZE,
+            "version ring out of bounds"
+        );
+        self.access(index, |val| {
+            let mut head_bytes = [0u8; 8];
+            head_bytes.copy_from_slice(&val[0..8]);
+            let head = u64::from_le_bytes(head_bytes);
+            let mut retval = [0u8; LEN];
+            for pos in 0..VERSIONS {
+                let offset = 8 + pos * LEN;
+                let mut version = [0u8; LEN];
+                version.copy_from_slice(&val[offset..offset + LEN]);
+                let test = (pos as u64).ct_eq(&head);
+                retval.cmov(test, &version);
+                version.cmov(test, new_version);
+                val[offset..offset + LEN].copy_from_slice(&version);
+            }
+            let mut next = head.wrapping_add(1);
+            next.cmov(next.ct_eq(&(VERSIONS as u64)), &0);
+            val[0..8].copy_from_slice(&next.to_le_bytes());
+            retval
+        })
+    }
+
+    /// High-level helper -- read the version of LEN bytes which was written
+    /// `back` versions ago, by `write_version`, so 0 is the latest version.
+    /// Versions which were never written, or which have been overwritten (back
+    /// is at least VERSIONS) read as zeroes.
+    ///
+    /// Every version is visited, so the access pattern doesn't reveal `back`.
+    ///
+    /// Panics if the ring does not fit in the value.
+    fn read_version<const LEN: usize, const VERSIONS: usize>(
+        &mut self,
+        index: u64,
+        back: u32,
+    ) -> [u8; LEN] {
+        assert!(VERSIONS > 0, "there must be at least one version");
+        assert!(
+            8 + VERSIONS * LEN <= ValueSize::USIZE,
+            "version ring out of bounds"
+        );
+        let num_versions = VERSIONS as u64;
+        let in_range = (back as u64).ct_lt(&num_versions);
+        let mut back = back as u64;
+        back.cmov(!in_range, &0);
+        self.access_ref(index, |val| {
+            let mut head_bytes = [0u8; 8];
+            head_bytes.copy_from_slice(&val[0..8]);
+            let head = u64::from_le_bytes(head_bytes);
+            // The latest version is just before the head, so this is in
+            // 0..2 * VERSIONS, and is reduced without division
+            let mut target = head.wrapping_add(num_versions - 1 - back);
+            let reduced = target.wrapping_sub(num_versions);
+            target.cmov(!target.ct_lt(&num_versions), &reduced);
+            let mut retval = [0u8; LEN];
+            for pos in 0..VERSIONS {
+                let offset = 8 + pos * LEN;
+                let mut version = [0u8; LEN];
+                version.copy_from_slice(&val[offset..offset + LEN]);
+                retval.cmov((pos as u64).ct_eq(&target) & in_range, &version);
+            }
+            retval
+        })
+    }
+
+    /// High-level helper -- for keeping a bounded list in each value, e.g. an
+    /// adjacency list, append an item of LEN bytes to the list, if it has fewer
+    /// than CAPACITY items, and return whether it was appended.
+    ///
+    /// The value starts with the little-endian u64 number of items in the list,
+    /// followed by CAPACITY items of LEN bytes each. A full list is unchanged.
+    /// Every item is visited, so the access pattern reveals neither the length
+    /// of the list nor whether the append succeeded.
+    ///
+    /// Panics if the list does not fit in the value.
+    fn append<const LEN: usize, const CAPACITY: usize>(
+        &mut self,
+        index: u64,
+        item: &[u8; LEN],
+    ) -> Choice {
+        assert!(8 + CAPACITY * LEN <= ValueSize::USIZE, "list out of bounds");
+        self.access(index, |val| {
+            let mut count_bytes = [0u8; 8];
+            count_bytes.copy_from_slice(&val[0..8]);
+            let mut count = u64::from_le_bytes(count_bytes);
+            let has_room = count.ct_lt(&(CAPACITY as u64));
+            for pos in 0..CAPACITY {
+                let offset = 8 + pos * LEN;
+                let mut slot = [0u8; LEN];
+                slot.copy_from_slice(&val[offset..offset + LEN]);
+                slot.cmov((pos as u64).ct_eq(&count), item);
+                val[offset..offset + LEN].copy_from_slice(&slot);
+            }
+            count.cmov(has_room, &count.wrapping_add(1));
+            val[0..8].copy_from_slice(&count.to_le_bytes());
+            has_room
+        })
+    }
+
+    /// High-level helper -- scan every value in the ORAM, and call the collector
+    /// with each value and a mask, which is set if the key field of the value is
+    /// in the range `[lo, hi]`. The key field is the little-endian u64 at
+    /// KEY_OFFSET.
+    ///
+    /// The collector is called exactly once per value, in index order, so which
+    /// values match is not revealed by the access pattern, as long as the
+    /// collector itself only uses the mask obliviously (e.g. with cmov).
+    ///
+    /// Panics if the key field does not fit in the value.
+    fn range_scan<const KEY_OFFSET: usize>(
+        &mut self,
+        lo: u64,
+        hi: u64,
+        mut collect: impl FnMut(&A64Bytes<ValueSize>, Choice),
+    ) {
+        assert!(
+            KEY_OFFSET + 8 <= ValueSize::USIZE,
+            "key field out of bounds"
+        );
+        for index in 0..self.len() {
+            self.access_ref(index, |val| {
+                let mut key_bytes = [0u8; 8];
+                key_bytes.copy_from_slice(&val[KEY_OFFSET..KEY_OFFSET + 8]);
+                let key = u64::from_le_bytes(key_bytes);
+                collect(val, !key.ct_lt(&lo) & !key.ct_gt(&hi))
+            });
+        }
+    }
+
+    /// High-level helper -- scan every value in the ORAM, and return the k
+    /// values with the largest keys, according to a key function, from largest
+    /// to smallest. Ties are won by the value with the smaller index.
+    /// If the ORAM has fewer than k values, all of them are returned.
+    ///
+    /// A running set of k candidates is kept, and each scanned value is passed
+    /// through all of it with conditional swaps, so neither the access pattern
+    /// nor the memory access pattern of the set reveals which values entered
+    /// it. For this to be oblivious, the key function must be constant-time.
+    fn top_k(
+        &mut self,
+        k: usize,
+        key_fn: impl Fn(&A64Bytes<ValueSize>) -> u64,
+    ) -> Vec<A64Bytes<ValueSize>> {
+        let k = core::cmp::min(k as u64, self.len()) as usize;
+        let mut keys = vec![0u64; k];
+        let mut vals: Vec<A64Bytes<ValueSize>> = vec![Default::default(); k];
+        let mut valid = vec![Choice::from(0); k];
+        for index in 0..self.len() {
+            self.access_ref(index, |val| {
+                let mut carry_key = key_fn(val);
+                let mut carry_val = val.clone();
+                let mut carry_valid = Choice::from(1);
+                for idx in 0..k {
+                    let test = carry_valid & (!valid[idx] | carry_key.ct_gt(&keys[idx]));
+                    cswap(test, &mut carry_key, &mut keys[idx]);
+                    cswap(test, &mut carry_val, &mut vals[idx]);
+                    Choice::conditional_swap(&mut carry_valid, &mut valid[idx], test);
+                }
+            });
+        }
+        vals
+    }
 }
 
 /// Trait that helps to debug ORAM.
@@ -547,6 +897,23 @@ pub const fn log2_ceil(arg: u64) -> u32 {
     (!0u64).count_ones() - (arg - 1).leading_zeros()
 }
 
+/// Utility function to get a canonical byte key for a block number (a
+/// TreeIndex in ORAMStorage), e.g. for storage backends built on a key-value
+/// store. Block numbers are structural and not secret.
+///
+/// The key is big-endian, so the byte-wise order of keys matches the numeric
+/// order of block numbers.
+#[inline]
+pub const fn block_key(block_num: u64) -> [u8; 8] {
+    block_num.to_be_bytes()
+}
+
+/// Utility function to recover the block number from a key made by `block_key`.
+#[inline]
+pub const fn block_num_from_key(key: [u8; 8]) -> u64 {
+    u64::from_be_bytes(key)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -564,4 +931,296 @@ mod test {
         assert_eq!(4, log2_ceil(16));
         assert_eq!(5, log2_ceil(17));
     }
+
+    // Test that block keys round trip, and sort in the same order as block numbers
+    #[test]
+    fn test_block_key() {
+        let block_nums = [
+            0u64,
+            1,
+            2,
+            255,
+            256,
+            257,
+            65535,
+            1 << 32,
+            u64::MAX - 1,
+            u64::MAX,
+        ];
+        for block_num in block_nums.iter() {
+            assert_eq!(block_num_from_key(block_key(*block_num)), *block_num);
+        }
+        for pair in block_nums.windows(2) {
+            assert!(block_key(pair[0]) < block_key(pair[1]));
+        }
+        assert_eq!(block_key(1), [0, 0, 0, 0, 0, 0, 0, 1]);
+    }
+
+    // A storage which only forwards checkout and checkin, so that it uses the
+    // default for_each_bucket
+    struct BranchOnlyStorage(HeapORAMStorage<typenum::U64, typenum::U8>);
+
+    impl ORAMStorage<typenum::U64, typenum::U8> for BranchOnlyStorage {
+        fn len(&self) -> u64 {
+            self.0.len()
+        }
+        fn checkout(
+            &mut self,
+            index: u64,
+            dest: &mut [A64Bytes<typenum::U64>],
+            dest_meta: &mut [A8Bytes<typenum::U8>],
+        ) {
+            self.0.checkout(index, dest, dest_meta)
+        }
+        fn checkin(
+            &mut self,
+            index: u64,
+            src: &mut [A64Bytes<typenum::U64>],
+            src_meta: &mut [A8Bytes<typenum::U8>],
+        ) {
+            self.0.checkin(index, src, src_meta)
+        }
+    }
+
+    // Test that for_each_bucket visits every bucket once and keeps changes,
+    // both in the default implementation and in HeapORAMStorage
+    #[test]
+    fn test_for_each_bucket() {
+        let mut heap = HeapORAMStorage::<typenum::U64, typenum::U8>::new(16);
+        let mut branch_only = BranchOnlyStorage(HeapORAMStorage::new(16));
+        let mut storages: [&mut dyn ORAMStorage<typenum::U64, typenum::U8>; 2] =
+            [&mut heap, &mut branch_only];
+        for storage in storages.iter_mut() {
+            let mut visited = Vec::new();
+            storage.for_each_bucket(&mut |index, data, meta| {
+                visited.push(index);
+                data[0] = index as u8;
+                meta[0] = index as u8 + 1;
+            });
+            visited.sort_unstable();
+            assert_eq!(visited, (1..16).collect::<Vec<u64>>());
+            for index in 1..16u64 {
+                assert_eq!(storage.read_bucket_meta(index)[0], index as u8 + 1);
+            }
+            storage.for_each_bucket(&mut |index, data, _| assert_eq!(data[0], index as u8));
+        }
+    }
+
+    // Test reading and updating a field in the middle of a value
+    #[test]
+    fn test_read_write_field() {
+        let mut oram = LinearScanningORAM::<typenum::U32>::new(4);
+        let mut val = A64Bytes::<typenum::U32>::default();
+        for (idx, byte) in val.iter_mut().enumerate() {
+            *byte = idx as u8;
+        }
+        oram.write(2, &val);
+
+        assert_eq!(oram.read_field::<8, 4>(2), [8, 9, 10, 11]);
+        assert_eq!(oram.write_field::<8, 4>(2, &[0xff; 4]), [8, 9, 10, 11]);
+        assert_eq!(oram.read_field::<8, 4>(2), [0xff; 4]);
+
+        // The neighboring bytes, and other values, are preserved
+        let result = oram.read(2);
+        for (idx, byte) in result.iter().enumerate() {
+            if (8..12).contains(&idx) {
+                assert_eq!(*byte, 0xff);
+            } else {
+                assert_eq!(*byte, idx as u8);
+            }
+        }
+        assert_eq!(oram.read(1), A64Bytes::<typenum::U32>::default());
+        assert_eq!(oram.read_field::<28, 4>(2), [28, 29, 30, 31]);
+    }
+
+    // Test that increment_and_check flips exactly when the limit is crossed,
+    // and only touches its own counter
+    #[test]
+    fn test_increment_and_check() {
+        let mut oram = LinearScanningORAM::<typenum::U32>::new(4);
+        for call in 1..=10u64 {
+            let exceeded = oram.increment_and_check::<8>(1, 5);
+            assert_eq!(bool::from(exceeded), call > 5);
+        }
+        assert_eq!(oram.read_field::<8, 8>(1), 10u64.to_le_bytes());
+        assert_eq!(oram.read_field::<0, 8>(1), [0u8; 8]);
+        assert_eq!(oram.read(2), A64Bytes::<typenum::U32>::default());
+
+        // A limit of zero is exceeded by the first call
+        assert!(bool::from(oram.increment_and_check::<0>(2, 0)));
+
+        // The counter saturates
+        oram.write_field::<24, 8>(3, &(u64::MAX - 1).to_le_bytes());
+        assert!(bool::from(oram.increment_and_check::<24>(3, u64::MAX - 1)));
+        assert!(!bool::from(oram.increment_and_check::<24>(3, u64::MAX)));
+        assert_eq!(oram.read_field::<24, 8>(3), u64::MAX.to_le_bytes());
+    }
+
+    // Test that each historical version can be read back, until it is
+    // overwritten by the ring wrapping around
+    #[test]
+    fn test_versioned_reads() {
+        let mut oram = LinearScanningORAM::<typenum::U64>::new(4);
+        assert_eq!(oram.read_version::<8, 4>(1, 0), [0u8; 8]);
+
+        for version in 1..=10u64 {
+            let old = oram.write_version::<8, 4>(1, &version.to_le_bytes());
+            let expected_old = version.saturating_sub(4);
+            assert_eq!(old, expected_old.to_le_bytes());
+            for back in 0..6u32 {
+                let expected = if (back as u64) < version.min(4) {
+                    version - back as u64
+                } else {
+                    0
+                };
+                assert_eq!(
+                    oram.read_version::<8, 4>(1, back),
+                    expected.to_le_bytes(),
+                    "version {}, back {}",
+                    version,
+                    back
+                );
+            }
+        }
+        assert_eq!(oram.read_version::<8, 4>(1, u32::MAX), [0u8; 8]);
+        // Other keys are untouched
+        assert_eq!(oram.read(2), A64Bytes::<typenum::U64>::default());
+    }
+
+    // Test that append fills a list up to its capacity, and then fails without
+    // changing it
+    #[test]
+    fn test_append() {
+        let mut oram = LinearScanningORAM::<typenum::U64>::new(4);
+        for item in 1..=3u32 {
+            assert!(bool::from(oram.append::<4, 3>(1, &item.to_le_bytes())));
+        }
+        let full = oram.read(1);
+        assert!(!bool::from(oram.append::<4, 3>(1, &7u32.to_le_bytes())));
+        assert!(!bool::from(oram.append::<4, 3>(1, &8u32.to_le_bytes())));
+        assert_eq!(oram.read(1), full);
+
+        assert_eq!(oram.read_field::<0, 8>(1), 3u64.to_le_bytes());
+        assert_eq!(oram.read_field::<8, 4>(1), 1u32.to_le_bytes());
+        assert_eq!(oram.read_field::<12, 4>(1), 2u32.to_le_bytes());
+        assert_eq!(oram.read_field::<16, 4>(1), 3u32.to_le_bytes());
+        assert_eq!(oram.read_field::<20, 44>(1), [0u8; 44]);
+
+        // Other lists are independent
+        assert!(bool::from(oram.append::<4, 3>(2, &9u32.to_le_bytes())));
+        assert_eq!(
+            oram.read_field::<0, 12>(2),
+            [1, 0, 0, 0, 0, 0, 0, 0, 9, 0, 0, 0]
+        );
+        assert_eq!(oram.read(1), full);
+    }
+
+    // Test that access_pair can swap two values, and handles equal keys
+    #[test]
+    fn test_access_pair() {
+        let mut oram = LinearScanningORAM::<typenum::U32>::new(16);
+        for idx in 0..16u64 {
+            let mut val = A64Bytes::<typenum::U32>::default();
+            val[0] = idx as u8;
+            oram.write(idx, &val);
+        }
+
+        // Conditionally swap, under both choices
+        oram.access_pair(3, 5, |a, b| cswap(1.into(), a, b));
+        assert_eq!(oram.read(3)[0], 5);
+        assert_eq!(oram.read(5)[0], 3);
+        oram.access_pair(3, 5, |a, b| cswap(0.into(), a, b));
+        assert_eq!(oram.read(3)[0], 5);
+        assert_eq!(oram.read(5)[0], 3);
+
+        // With equal keys, the first argument wins
+        oram.access_pair(7, 7, |a, b| {
+            assert_eq!(a, b);
+            a[0] = 70;
+            b[0] = 71;
+        });
+        assert_eq!(oram.read(7)[0], 70);
+    }
+
+    // Test that top_k agrees with sorting and taking the first k
+    #[test]
+    fn test_top_k() {
+        let mut oram = LinearScanningORAM::<typenum::U32>::new(64);
+        let mut keys = Vec::new();
+        for idx in 0..64u64 {
+            let mut val = A64Bytes::<typenum::U32>::default();
+            let key = idx.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 8;
+            val[..8].copy_from_slice(&key.to_le_bytes());
+            oram.write(idx, &val);
+            keys.push(key);
+        }
+        let key_fn = |val: &A64Bytes<typenum::U32>| {
+            let mut key_bytes = [0u8; 8];
+            key_bytes.copy_from_slice(&val[..8]);
+            u64::from_le_bytes(key_bytes)
+        };
+        keys.sort_unstable_by(|a, b| b.cmp(a));
+
+        for k in [0, 1, 5, 64, 100].iter() {
+            let top: Vec<u64> = oram.top_k(*k, key_fn).iter().map(key_fn).collect();
+            assert_eq!(&top[..], &keys[..core::cmp::min(*k, 64)]);
+        }
+    }
+
+    // Test that a range scan masks exactly the values whose key is in range
+    #[test]
+    fn test_range_scan() {
+        let mut oram = LinearScanningORAM::<typenum::U32>::new(16);
+        for idx in 0..16u64 {
+            let mut val = A64Bytes::<typenum::U32>::default();
+            // Keys are not in index order
+            let key = (idx * 7) % 16;
+            val[4..12].copy_from_slice(&key.to_le_bytes());
+            val[0] = idx as u8;
+            oram.write(idx, &val);
+        }
+
+        let mut calls = 0;
+        let mut collected = Vec::new();
+        oram.range_scan::<4>(3, 9, |val, mask| {
+            calls += 1;
+            if bool::from(mask) {
+                collected.push(val[0]);
+            }
+        });
+        assert_eq!(calls, 16);
+
+        let expected: Vec<u8> = (0..16u64)
+            .filter(|idx| (3..=9).contains(&((idx * 7) % 16)))
+            .map(|idx| idx as u8)
+            .collect();
+        assert_eq!(collected, expected);
+
+        // An empty range matches nothing
+        let mut matched = 0;
+        oram.range_scan::<4>(9, 3, |_, mask| matched += mask.unwrap_u8());
+        assert_eq!(matched, 0);
+    }
+
+    // Test that indexing a snapshot gives the same values as reading the ORAM
+    #[test]
+    fn test_oram_snapshot() {
+        let mut oram = LinearScanningORAM::<typenum::U32>::new(8);
+        for idx in 0..8u64 {
+            let mut val = A64Bytes::<typenum::U32>::default();
+            val[0] = idx as u8 * 3;
+            oram.write(idx, &val);
+        }
+
+        let snapshot = ORAMSnapshot::new(&mut oram);
+        assert_eq!(snapshot.len(), 8);
+        for idx in 0..8u64 {
+            assert_eq!(snapshot[idx], oram.read(idx));
+            assert_eq!(snapshot[idx][0], idx as u8 * 3);
+        }
+
+        // Later changes to the ORAM are not reflected in the snapshot
+        oram.write(5, &Default::default());
+        assert_eq!(snapshot[5][0], 15);
+    }
 }
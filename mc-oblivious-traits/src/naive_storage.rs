@@ -77,6 +77,57 @@ impl<BlockSize: ArrayLength<u8>, MetaSize: ArrayLength<u8>> ORAMStorage<BlockSiz
         }
         self.checkout_index = None;
     }
+    fn prewarm(&mut self) {
+        debug_assert!(self.checkout_index.is_none(), "prewarm during checkout");
+        // The vectors are initially zeroed, which typically means that the
+        // allocator gave us pages which are not yet mapped. Writing a byte of
+        // every page in place maps them, without allocating anything.
+        touch_pages(self.data.iter_mut().map(|block| &mut block[..]));
+        touch_pages(self.metadata.iter_mut().map(|meta| &mut meta[..]));
+    }
+    fn read_bucket_meta(&mut self, index: u64) -> A8Bytes<MetaSize> {
+        debug_assert!(self.checkout_index.is_none(), "read during checkout");
+        self.metadata[index as usize].clone()
+    }
+    fn for_each_bucket(
+        &mut self,
+        f: &mut dyn FnMut(u64, &mut A64Bytes<BlockSize>, &mut A8Bytes<MetaSize>),
+    ) {
+        debug_assert!(self.checkout_index.is_none(), "visit during checkout");
+        for (index, (data, meta)) in self
+            .data
+            .iter_mut()
+            .zip(self.metadata.iter_mut())
+            .enumerate()
+            .skip(1)
+        {
+            f(index as u64, data, meta);
+        }
+    }
+}
+
+/// The granularity at which prewarm touches memory
+const PAGE_SIZE: usize = 4096;
+
+/// Write back one byte of every PAGE_SIZE bytes of a sequence of contiguous
+/// chunks, so that every page they occupy is mapped. The contents are unchanged.
+fn touch_pages<'a>(chunks: impl Iterator<Item = &'a mut [u8]>) {
+    // The offset of the next byte to touch, within the current chunk
+    let mut next = 0usize;
+    for chunk in chunks {
+        while next < chunk.len() {
+            touch_byte(&mut chunk[next]);
+            next += PAGE_SIZE;
+        }
+        next -= chunk.len();
+    }
+}
+
+/// Write a byte back to itself, in a way that the compiler cannot elide
+#[allow(unsafe_code)]
+fn touch_byte(byte: &mut u8) {
+    // Safety: the pointer comes from a live, exclusive reference to a u8
+    unsafe { core::ptr::write_volatile(byte, core::ptr::read_volatile(byte)) }
 }
 
 /// HeapORAMStorage simply allocates a vector, and requires no special initialization support
@@ -0,0 +1,184 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! This module defines a wrapper around an ORAM which stores typed values,
+//! by serializing them with CBOR into the fixed-size ORAM values.
+
+use super::*;
+use core::{fmt, marker::PhantomData};
+use serde_cbor::ser::SliceWrite;
+use serde_crate::{de::DeserializeOwned, Serialize};
+
+/// An error which can occur when storing or loading a value in an ObliviousStore
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StoreError {
+    /// The serialized value does not fit in the ORAM value size
+    ValueTooLarge,
+    /// The value could not be serialized
+    Serialize,
+    /// The stored bytes could not be deserialized
+    Deserialize,
+    /// Nothing was ever written at this key
+    Missing,
+}
+
+impl Display for StoreError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::ValueTooLarge => write!(formatter, "Value too large for the ORAM"),
+            StoreError::Serialize => write!(formatter, "Value could not be serialized"),
+            StoreError::Deserialize => write!(formatter, "Value could not be deserialized"),
+            StoreError::Missing => write!(formatter, "No value was written at this key"),
+        }
+    }
+}
+
+// The marker in the first byte of a value which was written
+const PRESENT: u8 = 1;
+
+/// A wrapper around an ORAM which stores values of type T, serialized with
+/// CBOR into the ORAM's fixed-size values.
+///
+/// The ORAM accesses are exactly those of `ORAM::read` and `ORAM::write`.
+/// Serialization and deserialization happen outside the ORAM, and are not
+/// constant-time, so their timing may reveal something about the values.
+/// A value which doesn't serialize into the value size is rejected before
+/// the ORAM is accessed.
+///
+/// The first byte of each ORAM value marks whether a value was written, since
+/// the all-zero bytes of an unwritten key are themselves valid CBOR (the
+/// integer 0). The serialized value follows it, so one byte of the value size
+/// is not available to T.
+pub struct ObliviousStore<T, ValueSize, O>
+where
+    T: Serialize + DeserializeOwned,
+    ValueSize: ArrayLength<u8>,
+    O: ORAM<ValueSize>,
+{
+    oram: O,
+    _marker: PhantomData<fn() -> (T, ValueSize)>,
+}
+
+impl<T, ValueSize, O> ObliviousStore<T, ValueSize, O>
+where
+    T: Serialize + DeserializeOwned,
+    ValueSize: ArrayLength<u8>,
+    O: ORAM<ValueSize>,
+{
+    /// Wrap an ORAM
+    pub fn new(oram: O) -> Self {
+        Self {
+            oram,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Unwrap the underlying ORAM
+    pub fn into_inner(self) -> O {
+        self.oram
+    }
+
+    /// The number of keys in the store
+    pub fn len(&self) -> u64 {
+        self.oram.len()
+    }
+
+    /// Whether the store has no keys
+    pub fn is_empty(&self) -> bool {
+        self.oram.len() == 0
+    }
+
+    /// Store a value at a key. Panics if the key is out of bounds.
+    pub fn write(&mut self, key: u64, value: &T) -> Result<(), StoreError> {
+        let mut buffer = A64Bytes::<ValueSize>::default();
+        buffer[0] = PRESENT;
+        let mut serializer =
+            serde_cbor::Serializer::new(SliceWrite::new(&mut buffer.as_mut_slice()[1..]));
+        value.serialize(&mut serializer).map_err(|err| {
+            if err.is_scratch_too_small() {
+                StoreError::ValueTooLarge
+            } else {
+                StoreError::Serialize
+            }
+        })?;
+        self.oram.write(key, &buffer);
+        Ok(())
+    }
+
+    /// Load the value at a key. Panics if the key is out of bounds.
+    pub fn read(&mut self, key: u64) -> Result<T, StoreError> {
+        let buffer = self.oram.read(key);
+        if buffer[0] != PRESENT {
+            return Err(StoreError::Missing);
+        }
+        // The serialized value is followed by zero padding, which we don't
+        // try to parse
+        let mut deserializer = serde_cbor::Deserializer::from_slice(&buffer.as_slice()[1..]);
+        T::deserialize(&mut deserializer).map_err(|_| StoreError::Deserialize)
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use serde_crate::Deserialize;
+
+    #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+    #[serde(crate = "serde_crate")]
+    struct Account {
+        id: u32,
+        balance: u64,
+        frozen: bool,
+    }
+
+    #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+    #[serde(crate = "serde_crate")]
+    struct Large {
+        payload: [u64; 16],
+    }
+
+    // Test storing and loading a struct, and failing cleanly if it doesn't fit
+    #[test]
+    fn store_and_load_struct() {
+        let mut store = ObliviousStore::<Account, typenum::U64, _>::new(LinearScanningORAM::<
+            typenum::U64,
+        >::new(8));
+        let account = Account {
+            id: 7,
+            balance: 1_000_000,
+            frozen: false,
+        };
+        store.write(3, &account).unwrap();
+        assert_eq!(store.read(3), Ok(account.clone()));
+        assert_eq!(store.read(4), Err(StoreError::Missing));
+
+        let frozen = Account {
+            frozen: true,
+            ..account
+        };
+        store.write(3, &frozen).unwrap();
+        assert_eq!(store.read(3), Ok(frozen));
+
+        let mut large_store = ObliviousStore::<Large, typenum::U64, _>::new(store.into_inner());
+        let large = Large {
+            payload: [u64::MAX; 16],
+        };
+        assert_eq!(large_store.write(5, &large), Err(StoreError::ValueTooLarge));
+        // Nothing was written
+        assert_eq!(
+            large_store.into_inner().read(5),
+            A64Bytes::<typenum::U64>::default()
+        );
+    }
+
+    // Test that an unwritten key is missing, even for a type whose CBOR
+    // encoding of 0 is all-zero bytes, and that a written zero is not
+    #[test]
+    fn unwritten_key_is_missing() {
+        let mut store =
+            ObliviousStore::<u64, typenum::U64, _>::new(LinearScanningORAM::<typenum::U64>::new(8));
+        assert_eq!(store.read(2), Err(StoreError::Missing));
+        store.write(2, &0).unwrap();
+        assert_eq!(store.read(2), Ok(0));
+        assert_eq!(store.read(3), Err(StoreError::Missing));
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright (c) 2018-2021 The MobileCoin Foundation
+
+//! This module defines a read-only snapshot of the values in an ORAM,
+//! which supports indexing syntax, and a way to compare the contents of two
+//! ORAMs. These are mainly meant for test and maintenance code.
+
+use super::*;
+use core::ops::Index;
+
+/// A plain copy of every value in an ORAM, taken at one point in time.
+///
+/// Since reading an ORAM mutates it, `Index` can't be implemented on the ORAM
+/// itself. Instead, the snapshot reads each value once, in order, which is a
+/// fixed access pattern, and then serves lookups from its own copy.
+/// The snapshot does not see later changes to the ORAM.
+pub struct ORAMSnapshot<ValueSize: ArrayLength<u8>> {
+    data: Vec<A64Bytes<ValueSize>>,
+}
+
+impl<ValueSize: ArrayLength<u8>> ORAMSnapshot<ValueSize> {
+    /// Take a snapshot of an ORAM, by reading every value in it
+    pub fn new<O: ORAM<ValueSize>>(oram: &mut O) -> Self {
+        Self {
+            data: (0..oram.len()).map(|idx| oram.read(idx)).collect(),
+        }
+    }
+
+    /// The number of values in the snapshot
+    pub fn len(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    /// Whether the snapshot holds no values
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+impl<ValueSize: ArrayLength<u8>> Index<u64> for ORAMSnapshot<ValueSize> {
+    type Output = A64Bytes<ValueSize>;
+
+    /// Panics if the index is out of bounds
+    fn index(&self, index: u64) -> &Self::Output {
+        assert!(index < self.len(), "index out of bounds");
+        &self.data[index as usize]
+    }
+}
+
+/// Compare the logical contents of two ORAMs: they are equal if they have the
+/// same length, and every key maps to the same value in both. This doesn't
+/// depend on the internal layout of either ORAM.
+///
+/// This reads every key of both ORAMs in order, stopping at the first
+/// difference, so it reveals where the first difference is.
+/// It is meant for tests and maintenance, e.g. validating a migration.
+pub fn states_equal<ValueSize, A, B>(a: &mut A, b: &mut B) -> bool
+where
+    ValueSize: ArrayLength<u8>,
+    A: ORAM<ValueSize>,
+    B: ORAM<ValueSize>,
+{
+    a.len() == b.len() && (0..a.len()).all(|idx| a.read(idx) == b.read(idx))
+}
@@ -361,3 +361,64 @@ where
         num_rounds -= 1;
     }
 }
+
+/// An ORAM wrapper which folds every value it accesses, as found before the
+/// access changes it, into a running checksum. Two runs of the same workload,
+/// e.g. before and after an optimization, can then be compared by their final
+/// checksums, rather than by logging every value.
+///
+/// The checksum is FNV-1a over the accessed indices and values. It is only
+/// for detecting differences in tests, and is neither keyed nor oblivious.
+pub struct ChecksummingORAM<O> {
+    oram: O,
+    checksum: u64,
+}
+
+/// The FNV-1a offset basis
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+/// The FNV-1a prime
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+impl<O> ChecksummingORAM<O> {
+    /// Wrap an ORAM, starting from the checksum of nothing
+    pub fn new(oram: O) -> Self {
+        Self {
+            oram,
+            checksum: FNV_OFFSET_BASIS,
+        }
+    }
+
+    /// The checksum over every access so far
+    pub fn checksum(&self) -> u64 {
+        self.checksum
+    }
+
+    /// Unwrap the ORAM
+    pub fn into_inner(self) -> O {
+        self.oram
+    }
+
+    /// Fold some bytes into the checksum
+    fn fold(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.checksum ^= *byte as u64;
+            self.checksum = self.checksum.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+impl<ValueSize: ArrayLength<u8>, O: ORAM<ValueSize>> ORAM<ValueSize> for ChecksummingORAM<O> {
+    fn len(&self) -> u64 {
+        self.oram.len()
+    }
+    fn access<T, F: FnOnce(&mut A64Bytes<ValueSize>) -> T>(&mut self, index: u64, func: F) -> T {
+        let mut found = A64Bytes::<ValueSize>::default();
+        let result = self.oram.access(index, |val| {
+            found.clone_from_slice(val.as_slice());
+            func(val)
+        });
+        self.fold(&index.to_le_bytes());
+        self.fold(&found);
+        result
+    }
+}
@@ -1,26 +1,46 @@
 pub use rand_core::{CryptoRng, RngCore, SeedableRng};
 use rand_hc::Hc128Rng;
-type Seed = <RngType as SeedableRng>::Seed;
+use std::fmt::Debug;
+pub type Seed = <RngType as SeedableRng>::Seed;
 
 const NUM_TRIALS: usize = 3;
 
+// If this environment variable is set to a seed in hex, then
+// run_with_several_seeds only runs with that seed, to reproduce a failure.
+pub const SEED_ENV_VAR: &str = "MC_TEST_SEED";
+
 // Sometimes you need to have the type in scope to call trait functions
 pub type RngType = Hc128Rng;
 
 // Helper for running a unit test that requires randomness, but doing it
 // seeded and deterministically
+//
+// If a run panics, the seed is printed, and the failure can be reproduced
+// by setting MC_TEST_SEED to it.
 pub fn run_with_several_seeds<F: FnMut(RngType)>(mut f: F) {
-    for seed in &get_seeds() {
-        f(RngType::from_seed(*seed));
+    for seed in &seeds_to_run() {
+        run_with_seed(*seed, &mut f);
     }
 }
 
-pub fn run_with_one_seed<F: FnOnce(RngType)>(f: F) {
-    f(get_seeded_rng());
+// The seeds to run with: the one in MC_TEST_SEED if it is set, and otherwise
+// the ones from get_seeds
+fn seeds_to_run() -> Vec<Seed> {
+    match std::env::var(SEED_ENV_VAR) {
+        Ok(hex) => vec![parse_seed(&hex)
+            .unwrap_or_else(|| panic!("{} must be 64 hex digits, got {:?}", SEED_ENV_VAR, hex))],
+        Err(_) => get_seeds().to_vec(),
+    }
 }
 
-// TODO(chris): Can we store the result of this function in a const somehow?
-fn get_seeds() -> [Seed; NUM_TRIALS] {
+// Run a test with one specific seed, printing the seed if it panics
+pub fn run_with_seed<T, F: FnOnce(RngType) -> T>(seed: Seed, f: F) -> T {
+    let _guard = SeedReporter(seed);
+    f(RngType::from_seed(seed))
+}
+
+// The seeds used by run_with_several_seeds, unless MC_TEST_SEED is set
+pub fn get_seeds() -> [Seed; NUM_TRIALS] {
     let mut rng = get_seeded_rng();
 
     let mut result = [[0u8; 32]; NUM_TRIALS];
@@ -30,6 +50,255 @@ fn get_seeds() -> [Seed; NUM_TRIALS] {
     result
 }
 
+// Format a seed as hex, as accepted by MC_TEST_SEED
+pub fn format_seed(seed: &Seed) -> String {
+    seed.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Parse a seed from hex, as produced by format_seed
+pub fn parse_seed(hex: &str) -> Option<Seed> {
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut result = Seed::default();
+    for (idx, byte) in result.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * idx..2 * idx + 2], 16).ok()?;
+    }
+    Some(result)
+}
+
+// Prints the seed if the test panics while this is alive
+struct SeedReporter(Seed);
+
+impl Drop for SeedReporter {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            eprintln!(
+                "test failed with seed {} (rerun with {}=<seed> to reproduce)",
+                format_seed(&self.0),
+                SEED_ENV_VAR
+            );
+        }
+    }
+}
+
+pub fn run_with_one_seed<F: FnOnce(RngType)>(f: F) {
+    f(get_seeded_rng());
+}
+
+// Helper for cross-checking an oblivious implementation against a plaintext
+// reference implementation. For each of several seeds, `num_inputs` inputs are
+// generated from the rng, and both implementations must agree on every one.
+//
+// This makes it cheap to validate a new oblivious algorithm, as long as there
+// is a simple (non-constant-time) version of it to compare against.
+pub fn verify_oblivious<I, O, G, F, R>(
+    num_inputs: usize,
+    mut generate: G,
+    mut oblivious: F,
+    mut reference: R,
+) where
+    I: Debug,
+    O: Debug + PartialEq,
+    G: FnMut(&mut RngType) -> I,
+    F: FnMut(&I) -> O,
+    R: FnMut(&I) -> O,
+{
+    run_with_several_seeds(|mut rng| {
+        for _ in 0..num_inputs {
+            let input = generate(&mut rng);
+            assert_eq!(
+                oblivious(&input),
+                reference(&input),
+                "oblivious and reference implementations disagree on input: {:?}",
+                input
+            );
+        }
+    })
+}
+
+// Helper for randomized tests which apply a sequence of operations to some
+// state. For each of several seeds, a fresh state is made, and `num_ops` ops
+// are generated and applied to it. If applying an op panics, the failing
+// sequence is shrunk, and the test fails with a snippet giving the seed and
+// the ops, which can be pasted into a reproduction test.
+//
+// The snippet uses the Debug format of the ops, so it is valid Rust for
+// simple ops, e.g. enums of integers.
+pub fn run_ops_with_several_seeds<S, Op, I, G, A>(
+    num_ops: usize,
+    mut init: I,
+    mut generate: G,
+    mut apply: A,
+) where
+    Op: Clone + Debug,
+    I: FnMut(RngType) -> S,
+    G: FnMut(&mut RngType) -> Op,
+    A: FnMut(&mut S, &Op),
+{
+    for seed in &seeds_to_run() {
+        if let Some(repro) = find_failing_ops(*seed, num_ops, &mut init, &mut generate, &mut apply)
+        {
+            panic!("op sequence failed, to reproduce:\n{}", repro.snippet());
+        }
+    }
+}
+
+// A failing sequence of ops, found by find_failing_ops
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Reproduction<Op> {
+    // The seed of the test
+    pub seed: Seed,
+    // The ops, after shrinking. The last one is the one which panicked.
+    pub ops: Vec<Op>,
+}
+
+impl<Op: Debug> Reproduction<Op> {
+    // Format the seed and ops as a snippet for a reproduction test
+    pub fn snippet(&self) -> String {
+        let mut result = format!("// seed: {}\nlet ops = vec![\n", format_seed(&self.seed));
+        for op in &self.ops {
+            result += &format!("    {:?},\n", op);
+        }
+        result += "];\n";
+        result
+    }
+}
+
+// Generate `num_ops` ops from a seed, and apply them to a fresh state, as in
+// run_ops_with_several_seeds. If one panics, the ops up to it are shrunk, by
+// dropping each earlier op in turn, if the rest still panics when applied to a
+// fresh state, and the result is returned.
+//
+// The state is made from its own rng, derived from the seed, so it is the
+// same however many ops are generated.
+pub fn find_failing_ops<S, Op, I, G, A>(
+    seed: Seed,
+    num_ops: usize,
+    mut init: I,
+    mut generate: G,
+    mut apply: A,
+) -> Option<Reproduction<Op>>
+where
+    Op: Clone,
+    I: FnMut(RngType) -> S,
+    G: FnMut(&mut RngType) -> Op,
+    A: FnMut(&mut S, &Op),
+{
+    let mut rng = RngType::from_seed(seed);
+    let mut state_seed = Seed::default();
+    rng.fill_bytes(&mut state_seed);
+    let ops: Vec<Op> = (0..num_ops).map(|_| generate(&mut rng)).collect();
+
+    // Apply a sequence of ops to a fresh state, and return how many were
+    // applied before one panicked, if one did
+    let mut failure_len = |ops: &[Op]| -> Option<usize> {
+        let mut state = init(RngType::from_seed(state_seed));
+        for (idx, op) in ops.iter().enumerate() {
+            let result =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| apply(&mut state, op)));
+            if result.is_err() {
+                return Some(idx);
+            }
+        }
+        None
+    };
+
+    let mut ops = ops[..=failure_len(&ops)?].to_vec();
+    for idx in (0..ops.len() - 1).rev() {
+        let mut candidate = ops.clone();
+        candidate.remove(idx);
+        if let Some(failed_at) = failure_len(&candidate) {
+            candidate.truncate(failed_at + 1);
+            ops = candidate;
+        }
+    }
+    Some(Reproduction { seed, ops })
+}
+
 pub fn get_seeded_rng() -> RngType {
     RngType::from_seed([7u8; 32])
 }
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    // Test that seeds round trip through hex, and that bad hex is rejected
+    #[test]
+    fn seed_hex_round_trip() {
+        for seed in get_seeds().iter() {
+            assert_eq!(parse_seed(&format_seed(seed)), Some(*seed));
+        }
+        assert_eq!(format_seed(&[0xab; 32]), "ab".repeat(32));
+        assert_eq!(parse_seed("ab"), None);
+        assert_eq!(parse_seed(&"zz".repeat(32)), None);
+    }
+
+    // Test that a fixed seed gives the same results every time
+    #[test]
+    fn fixed_seed_is_deterministic() {
+        let seed = get_seeds()[1];
+        let first: Vec<u64> =
+            run_with_seed(seed, |mut rng| (0..8).map(|_| rng.next_u64()).collect());
+        let second: Vec<u64> =
+            run_with_seed(seed, |mut rng| (0..8).map(|_| rng.next_u64()).collect());
+        assert_eq!(first, second);
+
+        let other: Vec<u64> = run_with_seed(get_seeds()[2], |mut rng| {
+            (0..8).map(|_| rng.next_u64()).collect()
+        });
+        assert_ne!(first, other);
+    }
+
+    // A state which panics on the second 7 it is given, among numbers 0..10
+    fn count_sevens(num_sevens: &mut usize, op: &u8) {
+        if *op == 7 {
+            *num_sevens += 1;
+            assert!(*num_sevens < 2, "second seven");
+        }
+    }
+
+    // Test that a failing op sequence is found, and shrunk to the ops which
+    // matter, and that the snippet gives the seed and the ops
+    #[test]
+    fn failing_ops_are_shrunk() {
+        let seed = get_seeds()[0];
+        let repro = find_failing_ops(
+            seed,
+            1000,
+            |_| 0usize,
+            |rng| (rng.next_u32() % 10) as u8,
+            count_sevens,
+        )
+        .expect("no failure found");
+        assert_eq!(repro.seed, seed);
+        assert_eq!(repro.ops, vec![7, 7]);
+        assert_eq!(
+            repro.snippet(),
+            format!(
+                "// seed: {}\nlet ops = vec![\n    7,\n    7,\n];\n",
+                format_seed(&seed)
+            )
+        );
+
+        // Without a failure, nothing is reported
+        assert_eq!(
+            find_failing_ops(seed, 1000, |_| 0usize, |_| 3u8, count_sevens),
+            None
+        );
+    }
+
+    // Test that a failing op sequence fails the test, with the snippet
+    #[test]
+    #[should_panic(expected = "to reproduce:\n// seed: ")]
+    fn failing_ops_report_snippet() {
+        run_ops_with_several_seeds(
+            1000,
+            |_| 0usize,
+            |rng| (rng.next_u32() % 10) as u8,
+            count_sevens,
+        );
+    }
+}